@@ -1,15 +1,113 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::error::{ConfigError, ConfigField};
+use crate::hotkeys::Action;
+use crate::input::{InputBackendKind, VK_LCONTROL, VK_LSHIFT, VK_PERIOD, VK_W};
+use crate::keys;
+use crate::macros::MacroKind;
+use crate::steps::KeyRef;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+const APP_DIR_NAME: &str = "moonwalk-macros";
+const CONFIG_FILE_NAME: &str = "config.json";
+pub const MIN_PEAK_DELAY: f64 = 0.05;
+pub const MAX_PEAK_DELAY: f64 = 5.0;
+/// Bounds for `peak_delay_step`, in seconds
+pub const MIN_PEAK_DELAY_STEP: f64 = 0.001;
+pub const MAX_PEAK_DELAY_STEP: f64 = 0.5;
+/// Longest final stretch of a wait that may be spun instead of slept, in microseconds
+pub const MAX_SPIN_THRESHOLD_US: u64 = 20_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub emote_slot: u8,
     pub gear_slot: String,
     pub peak_delay: f64,
+    /// How far the peak delay hotkeys move `peak_delay`, in seconds
+    pub peak_delay_step: f64,
     pub unequip_after: bool,
     pub unshiftlock_after: bool,
-    pub shiftlock_key: ShiftlockKey,
-    pub com_hotkey: String,
-    pub clip_hotkey: String,
+    /// Which physical key performs each in-game action
+    pub keymap: GameKeymap,
+    /// Keys Wall Clip holds while in shiftlock
+    pub wall_clip_movement: Vec<MovementKey>,
+    pub timing: StepTimings,
+    /// How much of each wait is spun instead of slept for precise timing, in microseconds. 0 only sleeps.
+    pub spin_threshold_us: u64,
+    /// Key combo for each bound action, keyed by `Action::id`
+    pub hotkeys: BTreeMap<String, String>,
+    pub input_backend: InputBackendKind,
+    /// Only read from files saved by older versions, see `migrate_legacy_fields`
+    #[serde(flatten, skip_serializing)]
+    pub legacy: LegacyFields,
+}
+
+/// Delays inside the built-in macros, in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StepTimings {
+    /// How long each key press holds the key down
+    pub key_press_ms: u64,
+    /// Gap after opening the emote menu and after picking the emote
+    pub menu_gap_ms: u64,
+    /// Pause before unequipping or toggling shiftlock back off
+    pub undo_delay_ms: u64,
+    /// Gap between shiftlock and the movement keys, on the way in and out
+    pub shiftlock_gap_ms: u64,
+}
+
+impl Default for StepTimings {
+    fn default() -> Self {
+        Self {
+            key_press_ms: 10,
+            menu_gap_ms: 50,
+            undo_delay_ms: 100,
+            shiftlock_gap_ms: 20,
+        }
+    }
+}
+
+impl StepTimings {
+    /// Every field with its label and allowed range, shared by validation and the UI
+    pub fn fields_mut(&mut self) -> [(&'static str, RangeInclusive<u64>, &mut u64); 4] {
+        [
+            ("Key press duration", 1..=200, &mut self.key_press_ms),
+            ("Emote menu gap", 0..=1000, &mut self.menu_gap_ms),
+            ("Unequip/unshiftlock delay", 0..=2000, &mut self.undo_delay_ms),
+            ("Shiftlock to movement gap", 0..=1000, &mut self.shiftlock_gap_ms),
+        ]
+    }
+    
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (label, range, value) in self.clone().fields_mut() {
+            if !range.contains(value) {
+                let message = format!("{} must be between {}-{} ms", label, range.start(), range.end());
+                return Err(ConfigError::invalid(ConfigField::Timing, message));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn key_press(&self) -> Duration {
+        Duration::from_millis(self.key_press_ms)
+    }
+}
+
+/// Fields from older versions: one hotkey field per action, and a shiftlock choice before `keymap`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LegacyFields {
+    shiftlock_key: Option<ShiftlockKey>,
+    com_hotkey: Option<String>,
+    clip_hotkey: Option<String>,
+    abort_hotkey: Option<String>,
+    record_hotkey: Option<String>,
+    macro_hotkeys: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -18,51 +116,521 @@ pub enum ShiftlockKey {
     Control,
 }
 
+/// One key of a movement: held from `start_ms` into the movement for `hold_ms`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MovementKey {
+    pub key: KeyRef,
+    #[serde(default)]
+    pub start_ms: u64,
+    pub hold_ms: u64,
+}
+
+/// Keys a movement can use, with the label the UI shows for each
+pub const MOVEMENT_KEYS: [(KeyRef, &str); 5] = [
+    (KeyRef::MoveForward, "Forward"),
+    (KeyRef::MoveBack, "Back"),
+    (KeyRef::MoveLeft, "Left"),
+    (KeyRef::MoveRight, "Right"),
+    (KeyRef::Jump, "Jump"),
+];
+pub const MAX_MOVEMENT_START_MS: u64 = 2000;
+pub const MOVEMENT_HOLD_MS: RangeInclusive<u64> = 10..=5000;
+
+/// Physical keys for the in-game actions macros perform, stored by key name
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameKeymap {
+    #[serde(with = "vk_by_name")]
+    pub emote_menu: u16,
+    #[serde(with = "vk_by_name")]
+    pub move_forward: u16,
+    #[serde(with = "vk_by_name")]
+    pub move_back: u16,
+    #[serde(with = "vk_by_name")]
+    pub move_left: u16,
+    #[serde(with = "vk_by_name")]
+    pub move_right: u16,
+    #[serde(with = "vk_by_name")]
+    pub jump: u16,
+    #[serde(with = "vk_by_name")]
+    pub shiftlock: u16,
+}
+
+impl Default for GameKeymap {
+    fn default() -> Self {
+        Self {
+            emote_menu: VK_PERIOD,
+            move_forward: VK_W,
+            move_back: 0x53,  // S
+            move_left: 0x41,  // A
+            move_right: 0x44, // D
+            jump: 0x20,       // Space
+            shiftlock: VK_LSHIFT,
+        }
+    }
+}
+
+impl GameKeymap {
+    /// Every action with its label, in the order the UI lists them
+    pub fn fields_mut(&mut self) -> [(&'static str, &mut u16); 7] {
+        [
+            ("Emote menu", &mut self.emote_menu),
+            ("Move forward", &mut self.move_forward),
+            ("Move back", &mut self.move_back),
+            ("Move left", &mut self.move_left),
+            ("Move right", &mut self.move_right),
+            ("Jump", &mut self.jump),
+            ("Shiftlock", &mut self.shiftlock),
+        ]
+    }
+    
+    /// Keys a macro may be holding down, released when recovering from stuck keys
+    pub fn holdable_keys(&self) -> [u16; 6] {
+        [self.shiftlock, self.move_forward, self.move_back, self.move_left, self.move_right, self.jump]
+    }
+    
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut keymap = *self;
+        let fields = keymap.fields_mut();
+        for (i, (label, vk)) in fields.iter().enumerate() {
+            if let Some((other, _)) = fields[..i].iter().find(|(_, other_vk)| other_vk == vk) {
+                let message = format!("{} and {} are both mapped to '{}'", other, label, keys::vk_label(**vk));
+                return Err(ConfigError::invalid(ConfigField::Keymap, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serde helpers that store a virtual-key code as the key's name, like macro files do
+mod vk_by_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use crate::keys;
+    
+    pub fn serialize<S: Serializer>(vk_code: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&keys::vk_label(*vk_code))
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        keys::parse_vk(&name).ok_or_else(|| D::Error::custom(format!("unknown key '{}'", name)))
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             emote_slot: 1,
             gear_slot: "1".to_string(),
             peak_delay: 0.97,
+            peak_delay_step: 0.01,
             unequip_after: true,
             unshiftlock_after: false,
-            shiftlock_key: ShiftlockKey::Shift,
-            com_hotkey: "f7".to_string(),
-            clip_hotkey: "f8".to_string(),
+            keymap: GameKeymap::default(),
+            wall_clip_movement: vec![MovementKey { key: KeyRef::MoveForward, start_ms: 0, hold_ms: 250 }],
+            timing: StepTimings::default(),
+            spin_threshold_us: 2_000,
+            hotkeys: [
+                (Action::Run(MacroKind::ComOffset), "f7"),
+                (Action::Run(MacroKind::WallClip), "f8"),
+                (Action::Abort, "f9"),
+                (Action::ToggleRecording, "f10"),
+            ].into_iter().map(|(action, key)| (action.id(), key.to_string())).collect(),
+            input_backend: InputBackendKind::default(),
+            legacy: LegacyFields::default(),
         }
     }
 }
 
 impl Config {
-    pub fn validate_emote_slot(&self) -> Result<(), String> {
+    pub fn validate_emote_slot(&self) -> Result<(), ConfigError> {
         if !(1..=8).contains(&self.emote_slot) {
-            return Err("Emote slot must be between 1-8".to_string());
+            return Err(ConfigError::invalid(ConfigField::EmoteSlot, "Emote slot must be between 1-8"));
         }
         Ok(())
     }
     
-    pub fn validate_gear_slot(&self) -> Result<(), String> {
+    pub fn validate_gear_slot(&self) -> Result<(), ConfigError> {
         if self.gear_slot.len() != 1 {
-            return Err("Gear slot must be a single character".to_string());
+            return Err(ConfigError::invalid(ConfigField::GearSlot, "Gear slot must be a single character"));
         }
         let valid_keys = "1234567890";
         if !valid_keys.contains(&self.gear_slot) {
-            return Err("Gear slot must be 1-9 or 0".to_string());
+            return Err(ConfigError::invalid(ConfigField::GearSlot, "Gear slot must be 1-9 or 0"));
+        }
+        Ok(())
+    }
+    
+    pub fn validate_delay(&self) -> Result<(), ConfigError> {
+        if !(MIN_PEAK_DELAY..=MAX_PEAK_DELAY).contains(&self.peak_delay) {
+            return Err(ConfigError::invalid(ConfigField::PeakDelay, "Peak delay must be between 0.05-5.0 seconds"));
+        }
+        Ok(())
+    }
+    
+    pub fn validate_delay_step(&self) -> Result<(), ConfigError> {
+        if !(MIN_PEAK_DELAY_STEP..=MAX_PEAK_DELAY_STEP).contains(&self.peak_delay_step) {
+            return Err(ConfigError::invalid(ConfigField::PeakDelayStep, "Peak delay step must be between 0.001-0.5 seconds"));
         }
         Ok(())
     }
     
-    pub fn validate_delay(&self) -> Result<(), String> {
-        if !(0.05..=5.0).contains(&self.peak_delay) {
-            return Err("Peak delay must be between 0.05-5.0 seconds".to_string());
+    /// Moves `peak_delay` one step up (`steps` = 1) or down (-1), stopping at the
+    /// `validate_delay` bounds. Returns the new delay.
+    pub fn nudge_peak_delay(&mut self, steps: i32) -> Result<f64, ConfigError> {
+        self.validate_delay_step()?;
+        let limit = if steps > 0 { MAX_PEAK_DELAY } else { MIN_PEAK_DELAY };
+        if self.peak_delay == limit {
+            return Err(ConfigError::PeakDelayLimit { limit });
+        }
+        
+        let nudged = self.peak_delay + self.peak_delay_step * f64::from(steps);
+        // Rounded to whole milliseconds so repeated steps don't drift to 0.9700000001
+        self.peak_delay = ((nudged * 1000.0).round() / 1000.0).clamp(MIN_PEAK_DELAY, MAX_PEAK_DELAY);
+        Ok(self.peak_delay)
+    }
+    
+    pub fn validate_spin_threshold(&self) -> Result<(), ConfigError> {
+        if self.spin_threshold_us > MAX_SPIN_THRESHOLD_US {
+            return Err(ConfigError::invalid(ConfigField::SpinThreshold, "Spin threshold must be at most 20000 microseconds"));
         }
         Ok(())
     }
     
-    pub fn validate_all(&self) -> Result<(), String> {
+    pub fn validate_all(&self) -> Result<(), ConfigError> {
         self.validate_emote_slot()?;
         self.validate_gear_slot()?;
         self.validate_delay()?;
+        self.validate_spin_threshold()?;
+        self.timing.validate()?;
+        self.validate_keymap()?;
+        self.validate_wall_clip_movement()?;
+        Ok(())
+    }
+    
+    /// Checks the keymap on its own, then that every key stays clear of the emote and gear slot
+    /// digits and can be sent by the selected input backend
+    pub fn validate_keymap(&self) -> Result<(), ConfigError> {
+        self.keymap.validate()?;
+        
+        let slots = [
+            ("emote slot", KeyRef::EmoteSlot.resolve(self).ok()),
+            ("gear slot", KeyRef::GearSlot.resolve(self).ok()),
+        ];
+        let mut keymap = self.keymap;
+        for (label, vk) in keymap.fields_mut() {
+            let name = keys::vk_label(*vk);
+            if let Some((slot, _)) = slots.iter().find(|(_, slot_vk)| *slot_vk == Some(*vk)) {
+                let message = format!("{} is mapped to '{}', which is the {} key", label, name, slot);
+                return Err(ConfigError::invalid(ConfigField::Keymap, message));
+            }
+            if !self.input_backend.can_send(*vk) {
+                let message = format!("{} key '{}' can't be sent by the {} input backend", label, name, self.input_backend);
+                return Err(ConfigError::invalid(ConfigField::Keymap, message));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn validate_wall_clip_movement(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::invalid(ConfigField::WallClipMovement, message));
+        if self.wall_clip_movement.is_empty() {
+            return invalid("Wall clip movement needs at least one key".to_string());
+        }
+        for (i, movement) in self.wall_clip_movement.iter().enumerate() {
+            if !MOVEMENT_KEYS.iter().any(|(key, _)| *key == movement.key) {
+                return invalid(format!("Wall clip movement can't use '{}'", movement.key));
+            }
+            if self.wall_clip_movement[..i].iter().any(|other| other.key == movement.key) {
+                return invalid(format!("Wall clip movement lists '{}' twice", movement.key));
+            }
+            if movement.start_ms > MAX_MOVEMENT_START_MS {
+                return invalid(format!("Wall clip movement start must be at most {} ms", MAX_MOVEMENT_START_MS));
+            }
+            if !MOVEMENT_HOLD_MS.contains(&movement.hold_ms) {
+                return invalid(format!(
+                    "Wall clip movement hold must be between {}-{} ms",
+                    MOVEMENT_HOLD_MS.start(), MOVEMENT_HOLD_MS.end()
+                ));
+            }
+        }
         Ok(())
     }
+    
+    pub fn spin_threshold(&self) -> Duration {
+        Duration::from_micros(self.spin_threshold_us)
+    }
+    
+    /// Binding for `action`, if it has a non-empty one
+    pub fn hotkey(&self, action: &Action) -> Option<&str> {
+        self.hotkeys.get(&action.id()).map(|key| key.trim()).filter(|key| !key.is_empty())
+    }
+    
+    /// Moves settings read from an older file's fields into their current place:
+    /// per-action hotkeys into `hotkeys`, the shiftlock choice into `keymap`
+    pub fn migrate_legacy_fields(&mut self) {
+        let legacy = std::mem::take(&mut self.legacy);
+        match legacy.shiftlock_key {
+            Some(ShiftlockKey::Shift) => self.keymap.shiftlock = VK_LSHIFT,
+            Some(ShiftlockKey::Control) => self.keymap.shiftlock = VK_LCONTROL,
+            None => {}
+        }
+        
+        let fixed = [
+            (Action::Run(MacroKind::ComOffset), legacy.com_hotkey),
+            (Action::Run(MacroKind::WallClip), legacy.clip_hotkey),
+            (Action::Abort, legacy.abort_hotkey),
+            (Action::ToggleRecording, legacy.record_hotkey),
+        ];
+        
+        for (action, key) in fixed {
+            if let Some(key) = key {
+                self.hotkeys.insert(action.id(), key);
+            }
+        }
+        for (name, key) in legacy.macro_hotkeys {
+            self.hotkeys.insert(Action::UserMacro(name).id(), key);
+        }
+    }
+    
+    /// Location of the single-config file written before profiles existed
+    pub fn config_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+    }
+    
+    /// Loads a config file, falling back to defaults.
+    /// The second value is a warning to show the user when the file could not be used.
+    pub fn load_from(path: &Path) -> (Self, Option<ConfigError>) {
+        match read_json::<Self>(path) {
+            Ok(Some(mut config)) => {
+                config.migrate_legacy_fields();
+                (config, None)
+            }
+            Ok(None) => (Self::default(), None),
+            Err(e) => (Self::default(), Some(e)),
+        }
+    }
+}
+
+/// Per-user directory holding all of the app's saved files
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME))
+}
+
+/// Reads a JSON file, returning `Ok(None)` if it doesn't exist yet.
+/// A file that fails to parse is moved aside to `<name>.bak` so the next save doesn't clobber it.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, ConfigError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(ConfigError::Read { path: path.to_path_buf(), source }),
+    };
+    
+    match serde_json::from_str(&contents) {
+        Ok(value) => Ok(Some(value)),
+        Err(parse_err) => {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            
+            let path = path.to_path_buf();
+            match fs::rename(&path, &backup) {
+                Ok(()) => Err(ConfigError::Corrupt { path, backup, source: parse_err }),
+                Err(source) => Err(ConfigError::CorruptNoBackup { path, parse_error: parse_err, source }),
+            }
+        }
+    }
+}
+
+/// Writes a value as pretty JSON, going through a temp file so a crash mid-write can't corrupt it
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), ConfigError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|source| ConfigError::Write { path: dir.to_path_buf(), source })?;
+    }
+    
+    let json = serde_json::to_string_pretty(value).map_err(ConfigError::Serialize)?;
+    
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, json)
+        .map_err(|source| ConfigError::Write { path: tmp_path.clone(), source })?;
+    fs::rename(&tmp_path, path)
+        .map_err(|source| ConfigError::Write { path: path.to_path_buf(), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn old_fields_migrate_to_their_current_place() {
+        let json = r#"{"emote_slot": 3, "com_hotkey": "ctrl+f7", "abort_hotkey": "f12",
+            "macro_hotkeys": {"Spin": "f4"}, "shiftlock_key": "Control"}"#;
+        let mut config: Config = serde_json::from_str(json).unwrap();
+        config.migrate_legacy_fields();
+        
+        assert_eq!(config.emote_slot, 3);
+        assert_eq!(config.hotkey(&Action::Run(MacroKind::ComOffset)), Some("ctrl+f7"));
+        assert_eq!(config.hotkey(&Action::Run(MacroKind::WallClip)), Some("f8"));
+        assert_eq!(config.hotkey(&Action::Abort), Some("f12"));
+        assert_eq!(config.hotkey(&Action::UserMacro("Spin".to_string())), Some("f4"));
+        assert_eq!(config.keymap.shiftlock, VK_LCONTROL);
+        assert_eq!(config.legacy, LegacyFields::default());
+    }
+    
+    #[test]
+    fn saved_config_has_only_the_table() {
+        let json = serde_json::to_value(Config::default()).unwrap();
+        assert_eq!(json["hotkeys"]["com_offset"], "f7");
+        assert!(json.get("com_hotkey").is_none());
+        
+        let parsed: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, Config::default());
+    }
+    
+    #[test]
+    fn keymap_is_stored_by_key_name() {
+        let json = serde_json::to_value(Config::default()).unwrap();
+        assert_eq!(json["keymap"]["emote_menu"], "period");
+        assert_eq!(json["keymap"]["shiftlock"], "lshift");
+        assert!(json.get("shiftlock_key").is_none());
+        
+        let config: Config = serde_json::from_str(r#"{"keymap": {"move_forward": "z", "move_left": "q"}}"#).unwrap();
+        assert_eq!(config.keymap.move_forward, 0x5A);
+        assert_eq!(config.keymap.move_back, 0x53);
+        assert!(config.validate_all().is_ok());
+        
+        let clash = Config { keymap: GameKeymap { jump: 0x5A, ..config.keymap }, ..config };
+        let error = clash.validate_all().unwrap_err();
+        assert_eq!(error.field(), Some(ConfigField::Keymap));
+        assert_eq!(error.to_string(), "Move forward and Jump are both mapped to 'z'");
+    }
+    
+    #[test]
+    fn keymap_stays_clear_of_slots_and_unsendable_keys() {
+        let config = Config { emote_slot: 4, gear_slot: "2".to_string(), ..Config::default() };
+        assert!(config.validate_keymap().is_ok());
+        
+        let emote_clash = Config { keymap: GameKeymap { jump: 0x34, ..config.keymap }, ..config.clone() };
+        let error = emote_clash.validate_all().unwrap_err();
+        assert_eq!(error.field(), Some(ConfigField::Keymap));
+        assert_eq!(error.to_string(), "Jump is mapped to '4', which is the emote slot key");
+        
+        let gear_clash = Config { keymap: GameKeymap { emote_menu: 0x32, ..config.keymap }, ..config.clone() };
+        assert_eq!(gear_clash.validate_keymap().unwrap_err().to_string(), "Emote menu is mapped to '2', which is the gear slot key");
+        
+        // F13 has no enigo mapping, but SendInput passes any virtual-key code through
+        let f13 = GameKeymap { jump: 0x7C, ..config.keymap };
+        let enigo = Config { keymap: f13, input_backend: InputBackendKind::Enigo, ..config.clone() };
+        assert_eq!(enigo.validate_keymap().unwrap_err().to_string(), "Jump key 'f13' can't be sent by the enigo input backend");
+        let sendinput = Config { keymap: f13, input_backend: InputBackendKind::SendInput, ..config };
+        assert!(sendinput.validate_keymap().is_ok());
+    }
+    
+    #[test]
+    fn step_timings_default_and_validate() {
+        let config: Config = serde_json::from_str(r#"{"timing": {"undo_delay_ms": 300}}"#).unwrap();
+        assert_eq!(config.timing.undo_delay_ms, 300);
+        assert_eq!(config.timing.menu_gap_ms, 50);
+        assert!(config.validate_all().is_ok());
+        
+        let config = Config { timing: StepTimings { key_press_ms: 0, ..StepTimings::default() }, ..Config::default() };
+        let error = config.validate_all().unwrap_err();
+        assert_eq!(error.field(), Some(ConfigField::Timing));
+        assert_eq!(error.to_string(), "Key press duration must be between 1-200 ms");
+    }
+    
+    #[test]
+    fn wall_clip_movement_is_validated() {
+        let json = r#"{"wall_clip_movement": [
+            {"key": "move_forward", "hold_ms": 250},
+            {"key": "move_left", "start_ms": 40, "hold_ms": 120}
+        ]}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.wall_clip_movement[1], MovementKey { key: KeyRef::MoveLeft, start_ms: 40, hold_ms: 120 });
+        assert!(config.validate_all().is_ok());
+        
+        let mut twice = config.clone();
+        twice.wall_clip_movement[1].key = KeyRef::MoveForward;
+        let error = twice.validate_all().unwrap_err();
+        assert_eq!(error.field(), Some(ConfigField::WallClipMovement));
+        assert_eq!(error.to_string(), "Wall clip movement lists 'move_forward' twice");
+        
+        let mut other_key = config.clone();
+        other_key.wall_clip_movement[0].key = KeyRef::GearSlot;
+        assert!(other_key.validate_all().is_err());
+        
+        let empty = Config { wall_clip_movement: Vec::new(), ..config };
+        assert!(empty.validate_all().is_err());
+    }
+    
+    #[test]
+    fn peak_delay_nudges_stay_in_bounds() {
+        let mut config = Config::default();
+        assert_eq!(config.nudge_peak_delay(1).unwrap(), 0.98);
+        assert_eq!(config.nudge_peak_delay(-1).unwrap(), 0.97);
+        
+        config.peak_delay_step = 0.02;
+        config.peak_delay = 0.06;
+        assert_eq!(config.nudge_peak_delay(-1).unwrap(), MIN_PEAK_DELAY);
+        assert!(matches!(config.nudge_peak_delay(-1), Err(ConfigError::PeakDelayLimit { .. })));
+        assert!(config.validate_delay().is_ok());
+        
+        config.peak_delay_step = 2.0;
+        assert_eq!(config.nudge_peak_delay(1).unwrap_err().field(), Some(ConfigField::PeakDelayStep));
+        assert_eq!(config.peak_delay, MIN_PEAK_DELAY);
+    }
+    
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("moonwalk-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn corrupt_file_falls_back_to_defaults_and_is_kept() {
+        let dir = temp_dir("corrupt");
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, "{\"emote_slot\": 3,").unwrap();
+        
+        let (config, warning) = Config::load_from(&path);
+        let backup_contents = fs::read_to_string(dir.join("config.json.bak"));
+        let original_exists = path.exists();
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(config, Config::default());
+        assert!(matches!(warning, Some(ConfigError::Corrupt { .. })), "{:?}", warning);
+        assert_eq!(backup_contents.unwrap(), "{\"emote_slot\": 3,");
+        assert!(!original_exists);
+    }
+    
+    #[test]
+    fn missing_file_loads_defaults_without_warning() {
+        let dir = temp_dir("missing");
+        let path = dir.join(CONFIG_FILE_NAME);
+        
+        let read = read_json::<Config>(&path);
+        let (config, warning) = Config::load_from(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert!(matches!(read, Ok(None)));
+        assert_eq!(config, Config::default());
+        assert!(warning.is_none());
+    }
+    
+    #[test]
+    fn saved_config_loads_back() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("nested").join(CONFIG_FILE_NAME);
+        let config = Config { emote_slot: 5, peak_delay: 1.25, unequip_after: false, ..Config::default() };
+        
+        write_json(&path, &config).unwrap();
+        let (loaded, warning) = Config::load_from(&path);
+        let tmp_left_behind = path.with_extension("tmp").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(loaded, config);
+        assert!(warning.is_none());
+        assert!(!tmp_left_behind);
+    }
 }
@@ -0,0 +1,191 @@
+// Reacts to global hotkeys on its own thread, so a macro starts without waiting for the UI to repaint
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use eframe::egui;
+use global_hotkey::{GlobalHotKeyEvent, HotKeyState};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use crate::config::Config;
+use crate::error::MacroError;
+use crate::hotkeys::Action;
+use crate::user_macros::UserMacro;
+use crate::worker::WorkerHandle;
+
+#[derive(Debug)]
+pub enum DispatchEvent {
+    /// The dispatcher handled the action itself; `result` says whether the worker accepted it
+    Handled { action: Action, result: Result<(), MacroError> },
+    /// Recording, profile switches and settings changes belong to the UI, which gets to run them
+    Forwarded(Action),
+}
+
+/// What the dispatcher needs to act on a hotkey, kept up to date by the UI
+#[derive(Default)]
+struct Shared {
+    config: Config,
+    user_macros: Vec<UserMacro>,
+    /// Registered hotkey ids and what they trigger
+    bindings: HashMap<u32, Action>,
+}
+
+pub struct HotkeyDispatcher {
+    shared: Arc<Mutex<Shared>>,
+    events: Receiver<DispatchEvent>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotkeyDispatcher {
+    /// Starts listening on `hotkeys`, running macros through `worker` and waking `ctx` after each press
+    pub fn spawn(ctx: egui::Context, worker: WorkerHandle, hotkeys: Receiver<GlobalHotKeyEvent>) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (event_tx, event_rx) = unbounded();
+        let (stop_tx, stop_rx) = bounded(1);
+        
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("hotkey-dispatcher".to_string())
+            .spawn(move || {
+                loop {
+                    select! {
+                        recv(hotkeys) -> event => {
+                            let Ok(event) = event else { break };
+                            if event.state != HotKeyState::Pressed {
+                                continue;
+                            }
+                            // Stamped before anything else so the latency covers the whole path
+                            let triggered_at = Instant::now();
+                            if let Some(event) = dispatch(&thread_shared, &worker, event.id, triggered_at) {
+                                let _ = event_tx.send(event);
+                                ctx.request_repaint();
+                            }
+                        }
+                        recv(stop_rx) -> _ => break,
+                    }
+                }
+            })
+            .expect("failed to spawn hotkey dispatcher thread");
+        
+        Self {
+            shared,
+            events: event_rx,
+            stop: Some(stop_tx),
+            thread: Some(thread),
+        }
+    }
+    
+    fn shared(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    
+    /// Copies the active config and loaded macros over when they changed
+    pub fn sync(&self, config: &Config, user_macros: &[UserMacro]) {
+        let mut shared = self.shared();
+        if shared.config != *config {
+            shared.config = config.clone();
+        }
+        if shared.user_macros != user_macros {
+            shared.user_macros = user_macros.to_vec();
+        }
+    }
+    
+    /// Replaces the hotkey id to action table, call after registering hotkeys
+    pub fn set_bindings(&self, bindings: Vec<(u32, Action)>) {
+        self.shared().bindings = bindings.into_iter().collect();
+    }
+    
+    /// Drains everything the dispatcher did since the last call
+    pub fn poll(&self) -> Vec<DispatchEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for HotkeyDispatcher {
+    fn drop(&mut self) {
+        // The hotkey channel is global and never closes, so the thread has to be told to stop
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Acts on one hotkey press. Returns `None` for ids that aren't bound.
+fn dispatch(shared: &Mutex<Shared>, worker: &WorkerHandle, id: u32, triggered_at: Instant) -> Option<DispatchEvent> {
+    let shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+    let action = shared.bindings.get(&id)?.clone();
+    
+    let result = match &action {
+        Action::Run(kind) => worker.submit(&kind.to_string(), kind.steps(), shared.config.clone(), triggered_at),
+        Action::UserMacro(name) => match shared.user_macros.iter().find(|m| m.name == *name) {
+            Some(user_macro) => user_macro.validate_for(shared.config.input_backend).map_err(MacroError::from)
+                .and_then(|()| worker.submit(name, user_macro.steps.clone(), shared.config.clone(), triggered_at)),
+            None => Err(MacroError::NotLoaded(name.clone())),
+        },
+        // Nothing to report when there was nothing to abort
+        Action::Abort => return worker.abort().then_some(DispatchEvent::Handled { action, result: Ok(()) }),
+        Action::ReleaseAllKeys => worker.release_all_keys(&shared.config),
+        Action::ToggleRecording | Action::IncreasePeakDelay | Action::DecreasePeakDelay | Action::SwitchProfile(_) => {
+            return Some(DispatchEvent::Forwarded(action));
+        }
+    };
+    
+    Some(DispatchEvent::Handled { action, result })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::MacroWorker;
+    use std::time::Duration;
+    
+    fn pressed(id: u32) -> GlobalHotKeyEvent {
+        GlobalHotKeyEvent { id, state: HotKeyState::Pressed }
+    }
+    
+    fn next_event(dispatcher: &HotkeyDispatcher) -> Option<DispatchEvent> {
+        dispatcher.events.recv_timeout(Duration::from_millis(500)).ok()
+    }
+    
+    #[test]
+    fn presses_are_dispatched_without_the_ui() {
+        let worker = MacroWorker::spawn(egui::Context::default());
+        let (hotkeys, receiver) = unbounded();
+        let dispatcher = HotkeyDispatcher::spawn(egui::Context::default(), worker.handle(), receiver);
+        dispatcher.set_bindings(vec![
+            (1, Action::ToggleRecording),
+            (2, Action::UserMacro("Missing".to_string())),
+            (3, Action::Abort),
+        ]);
+        
+        hotkeys.send(GlobalHotKeyEvent { id: 1, state: HotKeyState::Released }).unwrap();
+        hotkeys.send(pressed(99)).unwrap();
+        hotkeys.send(pressed(3)).unwrap();
+        hotkeys.send(pressed(1)).unwrap();
+        assert!(matches!(next_event(&dispatcher), Some(DispatchEvent::Forwarded(Action::ToggleRecording))));
+        
+        hotkeys.send(pressed(2)).unwrap();
+        let event = next_event(&dispatcher);
+        assert!(matches!(
+            event,
+            Some(DispatchEvent::Handled { action: Action::UserMacro(_), result: Err(MacroError::NotLoaded(ref name)) }) if name == "Missing"
+        ), "{:?}", event);
+        assert!(dispatcher.poll().is_empty());
+    }
+    
+    #[test]
+    fn synced_macros_are_used() {
+        let worker = MacroWorker::spawn(egui::Context::default());
+        let (_hotkeys, receiver) = unbounded();
+        let dispatcher = HotkeyDispatcher::spawn(egui::Context::default(), worker.handle(), receiver);
+        let user_macro = UserMacro { name: "Idle".to_string(), description: String::new(), steps: Vec::new() };
+        dispatcher.set_bindings(vec![(7, Action::UserMacro("Idle".to_string()))]);
+        dispatcher.sync(&Config::default(), std::slice::from_ref(&user_macro));
+        
+        let event = dispatch(&dispatcher.shared, &worker.handle(), 7, Instant::now());
+        assert!(matches!(event, Some(DispatchEvent::Handled { action: Action::UserMacro(_), result: Ok(()) })), "{:?}", event);
+    }
+}
@@ -0,0 +1,231 @@
+// Typed errors for settings, hotkeys, input and macros, so callers can react to what went wrong
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use crate::hotkeys::Action;
+use crate::input::{InputBackendKind, MouseButton};
+use crate::steps::KeyRef;
+use crate::user_macros::MAX_WAIT_MS;
+
+/// Whatever a backend's own error type is, kept as the source
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// The setting an invalid config error is about, so the UI can point at it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigField {
+    EmoteSlot,
+    GearSlot,
+    PeakDelay,
+    PeakDelayStep,
+    SpinThreshold,
+    Timing,
+    Keymap,
+    WallClipMovement,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{message}")]
+    Invalid { field: ConfigField, message: String },
+    #[error("Peak delay is already at its {limit:.2} s limit")]
+    PeakDelayLimit { limit: f64 },
+    #[error("No config directory found")]
+    NoConfigDir,
+    #[error("Failed to read {}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    /// The file was moved aside to `backup` and defaults are used instead
+    #[error("{} is corrupt, using defaults. Old file kept as {}", path.display(), backup.display())]
+    Corrupt { path: PathBuf, backup: PathBuf, source: serde_json::Error },
+    #[error("{} is corrupt ({parse_error}) and could not be backed up", path.display())]
+    CorruptNoBackup { path: PathBuf, parse_error: serde_json::Error, source: io::Error },
+    #[error("Failed to serialize settings")]
+    Serialize(#[source] serde_json::Error),
+    #[error("Failed to write {}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl ConfigError {
+    pub fn invalid(field: ConfigField, message: impl Into<String>) -> Self {
+        Self::Invalid { field, message: message.into() }
+    }
+    
+    /// The setting to highlight, for validation errors
+    pub fn field(&self) -> Option<ConfigField> {
+        match self {
+            Self::Invalid { field, .. } => Some(*field),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ProfileError {
+    #[error("Profile name cannot be empty")]
+    EmptyName,
+    #[error("Profile '{0}' already exists")]
+    AlreadyExists(String),
+    #[error("Profile '{0}' does not exist")]
+    NotFound(String),
+    #[error("Cannot delete the last profile")]
+    LastProfile,
+}
+
+#[derive(Debug, Error)]
+pub enum HotkeyError {
+    #[error("Failed to create hotkey manager")]
+    Manager(#[source] global_hotkey::Error),
+    #[error("Hotkey manager not available")]
+    ManagerUnavailable,
+    #[error("Missing key in '{0}'")]
+    MissingKey(String),
+    #[error("Unknown modifier '{modifier}' in '{binding}'")]
+    UnknownModifier { modifier: String, binding: String },
+    #[error("Unsupported key: {0}")]
+    UnsupportedKey(String),
+    #[error("{0} can't be used as a global hotkey")]
+    NotBindable(String),
+    /// The binding for `action` didn't parse, `source` says why
+    #[error("{action} hotkey")]
+    Invalid { action: Action, source: Box<HotkeyError> },
+    #[error("{first} and {second} hotkeys are both '{binding}'")]
+    Duplicate { first: Action, second: Action, binding: String },
+    #[error("Failed to register {action} hotkey '{binding}'")]
+    Register { action: Action, binding: String, source: global_hotkey::Error },
+    #[error("{}; restoring the previous hotkeys also failed: {}", report(.error.as_ref()), report(.restore.as_ref()))]
+    RestoreFailed { error: Box<HotkeyError>, restore: Box<HotkeyError> },
+}
+
+impl HotkeyError {
+    /// The binding at fault, so the UI can point at its row
+    pub fn action(&self) -> Option<&Action> {
+        match self {
+            Self::Invalid { action, .. } | Self::Register { action, .. } => Some(action),
+            Self::Duplicate { second, .. } => Some(second),
+            Self::RestoreFailed { error, .. } => error.action(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InputError {
+    #[error("The {0} input backend is not supported on this platform")]
+    Unsupported(InputBackendKind),
+    #[error("Failed to initialize the {backend} input backend")]
+    Init { backend: &'static str, source: BoxError },
+    #[error("VK {vk_code:#04x} has no {backend} key mapping")]
+    Unmapped { vk_code: u16, backend: &'static str },
+    /// `what` is the event, such as "key input for VK 87"
+    #[error("Failed to send {what}")]
+    Send { what: String, source: BoxError },
+}
+
+#[derive(Debug, Error)]
+pub enum MacroError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Input(#[from] InputError),
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("{0} is still running")]
+    Busy(String),
+    #[error("Macro '{0}' is not loaded")]
+    NotLoaded(String),
+    #[error(transparent)]
+    UserMacro(#[from] UserMacroError),
+    #[error("Macro worker has stopped")]
+    WorkerStopped,
+    #[error("Macro panicked: {0}")]
+    Panicked(String),
+}
+
+/// A key name in a macro file or setting that isn't a key or a config key
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown key '{0}'")]
+pub struct UnknownKey(pub String);
+
+/// Problems with a user macro file. Field paths such as `steps[2].steps[0]` point into the file.
+#[derive(Debug, Error)]
+pub enum UserMacroError {
+    #[error("Failed to read the file")]
+    Read(#[source] io::Error),
+    /// Bad JSON or a macro-level field, serde's message carries the line and column
+    #[error(transparent)]
+    Syntax(serde_json::Error),
+    #[error("steps[{index}]")]
+    Step { index: usize, source: serde_json::Error },
+    #[error("name: must not be empty")]
+    EmptyName,
+    #[error("name: '{0}' is reserved for a built-in macro")]
+    ReservedName(String),
+    #[error("steps: must contain at least one step")]
+    NoSteps,
+    #[error("{path}.ms: {ms} ms is longer than the {MAX_WAIT_MS} ms limit")]
+    WaitTooLong { path: String, ms: u64 },
+    /// `path` ends in the offending field, such as `steps[1].dx`
+    #[error("{path}: {value} is outside the range of -{max} to {max}")]
+    OutOfRange { path: String, value: i32, max: i32 },
+    #[error("{path}.key: '{key}' is released without being held by an earlier key_down")]
+    UnheldKey { path: String, key: KeyRef },
+    #[error("{path}.button: '{button}' is released without being held by an earlier mouse_down")]
+    UnheldButton { path: String, button: MouseButton },
+    #[error("{path}.key: '{key}' can't be sent by the {backend} input backend")]
+    Unsendable { path: String, key: KeyRef, backend: InputBackendKind },
+    #[error("a macro named '{0}' is already loaded")]
+    DuplicateName(String),
+    #[error("{} already exists", .0.display())]
+    AlreadyExists(PathBuf),
+    #[error(transparent)]
+    Save(#[from] ConfigError),
+}
+
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    #[error("Could not open the keyboard for recording")]
+    NoKeyboard,
+    #[error("Failed to start recorder thread")]
+    Spawn(#[source] io::Error),
+    #[error("Recorder thread exited unexpectedly")]
+    ThreadExited,
+}
+
+/// An error followed by each of its sources, e.g. "Failed to read a.json: permission denied"
+pub fn report(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn report_follows_the_source_chain() {
+        let error = ConfigError::Read {
+            path: PathBuf::from("profiles.json"),
+            source: io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"),
+        };
+        assert_eq!(report(&error), "Failed to read profiles.json: permission denied");
+        
+        let error = MacroError::from(InputError::Send { what: "scroll".to_string(), source: "device gone".into() });
+        assert_eq!(report(&error), "Failed to send scroll: device gone");
+    }
+    
+    #[test]
+    fn hotkey_errors_name_the_binding() {
+        let error = HotkeyError::Invalid {
+            action: Action::Abort,
+            source: Box::new(HotkeyError::UnsupportedKey("f99".to_string())),
+        };
+        assert_eq!(report(&error), "Abort hotkey: Unsupported key: f99");
+        assert_eq!(error.action(), Some(&Action::Abort));
+    }
+}
@@ -1,129 +1,408 @@
-use global_hotkey::{GlobalHotKeyManager, HotKeyState, GlobalHotKeyEvent, hotkey::{HotKey, Code}};
-use crossbeam_channel::Receiver;
+use global_hotkey::{GlobalHotKeyManager, hotkey::{HotKey, Code, Modifiers}};
+use eframe::egui;
+use std::fmt;
+use crate::error::HotkeyError;
+use crate::keys;
+use crate::macros::MacroKind;
 
-pub struct HotkeyManager {
-    manager: GlobalHotKeyManager,
-    receiver: Receiver<GlobalHotKeyEvent>,
-    com_hotkey_id: Option<u32>,
-    clip_hotkey_id: Option<u32>,
+/// Where hotkeys get registered with the OS; tests substitute a fake
+pub trait HotkeyRegistry {
+    fn register(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error>;
+    fn unregister(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error>;
+}
+
+impl HotkeyRegistry for GlobalHotKeyManager {
+    fn register(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error> {
+        GlobalHotKeyManager::register(self, hotkey)
+    }
+    
+    fn unregister(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error> {
+        GlobalHotKeyManager::unregister(self, hotkey)
+    }
+}
+
+/// Something a hotkey can be bound to. The config stores bindings under `Action::id`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Run(MacroKind),
+    Abort,
+    ReleaseAllKeys,
+    ToggleRecording,
+    /// Nudge the active profile's peak delay by its configured step
+    IncreasePeakDelay,
+    DecreasePeakDelay,
+    /// A user-defined macro, by name
+    UserMacro(String),
+    SwitchProfile(String),
+}
+
+impl Action {
+    /// Actions that always exist, in the order the UI lists them
+    pub const BUILTIN: [Action; 7] = [
+        Action::Run(MacroKind::ComOffset),
+        Action::Run(MacroKind::WallClip),
+        Action::Abort,
+        Action::ReleaseAllKeys,
+        Action::ToggleRecording,
+        Action::IncreasePeakDelay,
+        Action::DecreasePeakDelay,
+    ];
+    
+    pub fn id(&self) -> String {
+        match self {
+            Self::Run(MacroKind::ComOffset) => "com_offset".to_string(),
+            Self::Run(MacroKind::WallClip) => "wall_clip".to_string(),
+            Self::Abort => "abort".to_string(),
+            Self::ReleaseAllKeys => "release_all_keys".to_string(),
+            Self::ToggleRecording => "record".to_string(),
+            Self::IncreasePeakDelay => "peak_delay_up".to_string(),
+            Self::DecreasePeakDelay => "peak_delay_down".to_string(),
+            Self::UserMacro(name) => format!("macro:{}", name),
+            Self::SwitchProfile(name) => format!("profile:{}", name),
+        }
+    }
+    
+    pub fn from_id(id: &str) -> Option<Self> {
+        if let Some(name) = id.strip_prefix("macro:") {
+            return Some(Self::UserMacro(name.to_string()));
+        }
+        if let Some(name) = id.strip_prefix("profile:") {
+            return Some(Self::SwitchProfile(name.to_string()));
+        }
+        Self::BUILTIN.into_iter().find(|action| action.id() == id)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Run(kind) => write!(f, "{}", kind),
+            Self::Abort => write!(f, "Abort"),
+            Self::ReleaseAllKeys => write!(f, "Release All Keys"),
+            Self::ToggleRecording => write!(f, "Record"),
+            Self::IncreasePeakDelay => write!(f, "Peak Delay +"),
+            Self::DecreasePeakDelay => write!(f, "Peak Delay -"),
+            Self::UserMacro(name) => write!(f, "Macro '{}'", name),
+            Self::SwitchProfile(name) => write!(f, "Profile '{}'", name),
+        }
+    }
+}
+
+/// One registered hotkey: what the user typed, what it parsed to and what it triggers
+#[derive(Debug, Clone)]
+struct Binding {
+    text: String,
+    hotkey: HotKey,
+    action: Action,
+}
+
+pub struct HotkeyManager<R: HotkeyRegistry = GlobalHotKeyManager> {
+    manager: R,
+    registered: Vec<Binding>,
 }
 
 impl HotkeyManager {
-    pub fn new() -> Result<Self, String> {
-        let manager = GlobalHotKeyManager::new()
-            .map_err(|e| format!("Failed to create hotkey manager: {}", e))?;
-        
-        let receiver = GlobalHotKeyEvent::receiver().clone();
-        
-        Ok(Self { 
-            manager, 
-            receiver,
-            com_hotkey_id: None,
-            clip_hotkey_id: None,
-        })
+    pub fn new() -> Result<Self, HotkeyError> {
+        let manager = GlobalHotKeyManager::new().map_err(HotkeyError::Manager)?;
+        Ok(Self::with_registry(manager))
+    }
+}
+
+impl<R: HotkeyRegistry> HotkeyManager<R> {
+    pub fn with_registry(manager: R) -> Self {
+        Self {
+            manager,
+            registered: Vec::new(),
+        }
     }
     
-    pub fn register_hotkeys(&mut self, com_hotkey: &str, clip_hotkey: &str) -> Result<(), String> {
-        // Unregister existing hotkeys if any
-        self.unregister_all();
+    /// Replaces the registered hotkeys with `bindings` (action and key combo). Either every binding
+    /// ends up registered, or the previous set is restored and the error names the binding that failed.
+    pub fn register_hotkeys(&mut self, bindings: &[(Action, String)]) -> Result<(), HotkeyError> {
+        // Parse and check everything up front so a typo leaves the current hotkeys alone
+        let mut parsed: Vec<Binding> = Vec::new();
+        for (action, text) in bindings {
+            let hotkey = parse_hotkey(text)
+                .map_err(|e| HotkeyError::Invalid { action: action.clone(), source: Box::new(e) })?;
+            if let Some(other) = parsed.iter().find(|b| b.hotkey == hotkey) {
+                return Err(HotkeyError::Duplicate {
+                    first: other.action.clone(),
+                    second: action.clone(),
+                    binding: text.trim().to_string(),
+                });
+            }
+            parsed.push(Binding { text: text.trim().to_string(), hotkey, action: action.clone() });
+        }
         
-        // Parse and register COM hotkey
-        let com_code = self.parse_key_string(com_hotkey)?;
-        let com_hotkey = HotKey::new(None, com_code);
-        self.manager.register(com_hotkey.clone())
-            .map_err(|e| format!("Failed to register COM hotkey: {}", e))?;
-        self.com_hotkey_id = Some(com_hotkey.id());
+        let previous = std::mem::take(&mut self.registered);
+        self.unregister(&previous);
         
-        // Parse and register Clip hotkey  
-        let clip_code = self.parse_key_string(clip_hotkey)?;
-        let clip_hotkey = HotKey::new(None, clip_code);
-        self.manager.register(clip_hotkey.clone())
-            .map_err(|e| format!("Failed to register Clip hotkey: {}", e))?;
-        self.clip_hotkey_id = Some(clip_hotkey.id());
+        if let Err(e) = self.register(&parsed) {
+            // Put the old set back so a failed apply changes nothing
+            return match self.register(&previous) {
+                Ok(()) => {
+                    self.registered = previous;
+                    Err(e)
+                }
+                Err(restore) => Err(HotkeyError::RestoreFailed { error: Box::new(e), restore: Box::new(restore) }),
+            };
+        }
         
+        self.registered = parsed;
+        Ok(())
+    }
+    
+    /// Registers all of `bindings` or none of them
+    fn register(&self, bindings: &[Binding]) -> Result<(), HotkeyError> {
+        for (i, binding) in bindings.iter().enumerate() {
+            if let Err(source) = self.manager.register(binding.hotkey) {
+                self.unregister(&bindings[..i]);
+                return Err(HotkeyError::Register { action: binding.action.clone(), binding: binding.text.clone(), source });
+            }
+        }
         Ok(())
     }
     
-    fn unregister_all(&mut self) {
-        // Simply reset the IDs since we'll re-register everything
-        // The global-hotkey crate will handle cleanup when the manager is dropped
-        self.com_hotkey_id = None;
-        self.clip_hotkey_id = None;
-    }
-    
-    fn parse_key_string(&self, key_str: &str) -> Result<Code, String> {
-        match key_str.to_lowercase().as_str() {
-            "f1" => Ok(Code::F1),
-            "f2" => Ok(Code::F2),
-            "f3" => Ok(Code::F3),
-            "f4" => Ok(Code::F4),
-            "f5" => Ok(Code::F5),
-            "f6" => Ok(Code::F6),
-            "f7" => Ok(Code::F7),
-            "f8" => Ok(Code::F8),
-            "f9" => Ok(Code::F9),
-            "f10" => Ok(Code::F10),
-            "f11" => Ok(Code::F11),
-            "f12" => Ok(Code::F12),
-            "a" => Ok(Code::KeyA),
-            "b" => Ok(Code::KeyB),
-            "c" => Ok(Code::KeyC),
-            "d" => Ok(Code::KeyD),
-            "e" => Ok(Code::KeyE),
-            "f" => Ok(Code::KeyF),
-            "g" => Ok(Code::KeyG),
-            "h" => Ok(Code::KeyH),
-            "i" => Ok(Code::KeyI),
-            "j" => Ok(Code::KeyJ),
-            "k" => Ok(Code::KeyK),
-            "l" => Ok(Code::KeyL),
-            "m" => Ok(Code::KeyM),
-            "n" => Ok(Code::KeyN),
-            "o" => Ok(Code::KeyO),
-            "p" => Ok(Code::KeyP),
-            "q" => Ok(Code::KeyQ),
-            "r" => Ok(Code::KeyR),
-            "s" => Ok(Code::KeyS),
-            "t" => Ok(Code::KeyT),
-            "u" => Ok(Code::KeyU),
-            "v" => Ok(Code::KeyV),
-            "w" => Ok(Code::KeyW),
-            "x" => Ok(Code::KeyX),
-            "y" => Ok(Code::KeyY),
-            "z" => Ok(Code::KeyZ),
-            "1" => Ok(Code::Digit1),
-            "2" => Ok(Code::Digit2),
-            "3" => Ok(Code::Digit3),
-            "4" => Ok(Code::Digit4),
-            "5" => Ok(Code::Digit5),
-            "6" => Ok(Code::Digit6),
-            "7" => Ok(Code::Digit7),
-            "8" => Ok(Code::Digit8),
-            "9" => Ok(Code::Digit9),
-            "0" => Ok(Code::Digit0),
-            _ => Err(format!("Unsupported key: {}", key_str)),
+    fn unregister(&self, bindings: &[Binding]) {
+        for binding in bindings {
+            // Nothing useful to do if the OS already forgot it
+            let _ = self.manager.unregister(binding.hotkey);
+        }
+    }
+    
+    /// Hotkey id and action of every registered binding, for matching `GlobalHotKeyEvent`s
+    pub fn bindings(&self) -> Vec<(u32, Action)> {
+        self.registered.iter().map(|b| (b.hotkey.id(), b.action.clone())).collect()
+    }
+}
+
+/// Parses a binding such as `f7` or `ctrl+shift+f7`. Modifiers come first, in any order, case-insensitive.
+pub fn parse_hotkey(binding: &str) -> Result<HotKey, HotkeyError> {
+    let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+    let key = parts.pop().filter(|key| !key.is_empty())
+        .ok_or_else(|| HotkeyError::MissingKey(binding.to_string()))?;
+    
+    let mut modifiers = Modifiers::empty();
+    for part in parts {
+        modifiers |= match part.to_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CONTROL,
+            "alt" | "option" => Modifiers::ALT,
+            "shift" => Modifiers::SHIFT,
+            "super" | "win" | "cmd" | "command" => Modifiers::SUPER,
+            _ => return Err(HotkeyError::UnknownModifier { modifier: part.to_string(), binding: binding.to_string() }),
+        };
+    }
+    
+    let code = parse_key_string(key)?;
+    Ok(HotKey::new(Some(modifiers), code))
+}
+
+/// Writes a binding in the canonical `ctrl+alt+shift+super+key` form that `parse_hotkey` reads back
+pub fn format_hotkey(modifiers: Modifiers, key: &str) -> String {
+    let names = [
+        (Modifiers::CONTROL, "ctrl"),
+        (Modifiers::ALT, "alt"),
+        (Modifiers::SHIFT, "shift"),
+        (Modifiers::SUPER, "super"),
+    ];
+    let mut parts: Vec<&str> = names.iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .map(|(_, name)| *name)
+        .collect();
+    parts.push(key);
+    parts.join("+").to_lowercase()
+}
+
+/// Binding for a key pressed in the UI while capturing a hotkey. egui only reports the logo key
+/// on macOS (as `mac_cmd`, `command` just mirrors ctrl elsewhere), so `logo_down` covers the rest.
+pub fn binding_from_egui(modifiers: egui::Modifiers, key: egui::Key, logo_down: bool) -> Result<String, HotkeyError> {
+    let name = keys::egui_key_name(key)
+        .ok_or_else(|| HotkeyError::NotBindable(key.name().to_string()))?;
+    
+    let mut mods = Modifiers::empty();
+    mods.set(Modifiers::CONTROL, modifiers.ctrl);
+    mods.set(Modifiers::ALT, modifiers.alt);
+    mods.set(Modifiers::SHIFT, modifiers.shift);
+    mods.set(Modifiers::SUPER, modifiers.mac_cmd || logo_down);
+    Ok(format_hotkey(mods, name))
+}
+
+fn parse_key_string(key_str: &str) -> Result<Code, HotkeyError> {
+    keys::hotkey_code(key_str).ok_or_else(|| HotkeyError::UnsupportedKey(key_str.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    use std::cell::RefCell;
+    
+    /// Behaves like the OS: a hotkey can only be registered once
+    #[derive(Default)]
+    struct FakeRegistry {
+        registered: RefCell<Vec<HotKey>>,
+        /// Can grow mid-test, like another app grabbing a key while it is briefly unregistered
+        taken_by_other_app: RefCell<Vec<HotKey>>,
+    }
+    
+    impl HotkeyRegistry for FakeRegistry {
+        fn register(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error> {
+            let mut registered = self.registered.borrow_mut();
+            if registered.contains(&hotkey) || self.taken_by_other_app.borrow().contains(&hotkey) {
+                return Err(global_hotkey::Error::FailedToRegister("already registered".to_string()));
+            }
+            registered.push(hotkey);
+            Ok(())
+        }
+        
+        fn unregister(&self, hotkey: HotKey) -> Result<(), global_hotkey::Error> {
+            self.registered.borrow_mut().retain(|h| *h != hotkey);
+            Ok(())
         }
     }
     
-    pub fn check_events(&self) -> Vec<HotkeyEvent> {
-        let mut events = Vec::new();
+    #[test]
+    fn action_ids_round_trip() {
+        let mut actions = Action::BUILTIN.to_vec();
+        actions.push(Action::UserMacro("Spin: fast".to_string()));
+        actions.push(Action::SwitchProfile("Alt".to_string()));
         
-        while let Ok(event) = self.receiver.try_recv() {
-            if event.state == HotKeyState::Pressed {
-                if Some(event.id) == self.com_hotkey_id {
-                    events.push(HotkeyEvent::ComOffset);
-                } else if Some(event.id) == self.clip_hotkey_id {
-                    events.push(HotkeyEvent::WallClip);
-                }
+        for action in actions {
+            assert_eq!(Action::from_id(&action.id()), Some(action));
+        }
+        assert_eq!(Action::from_id("nonsense"), None);
+    }
+    
+    fn registered(manager: &HotkeyManager<FakeRegistry>) -> Vec<HotKey> {
+        manager.manager.registered.borrow().clone()
+    }
+    
+    /// Registers COM Offset, Wall Clip and Abort, returning the full error report on failure
+    fn apply(manager: &mut HotkeyManager<FakeRegistry>, com: &str, clip: &str) -> Result<(), String> {
+        manager.register_hotkeys(&[
+            (Action::Run(MacroKind::ComOffset), com.to_string()),
+            (Action::Run(MacroKind::WallClip), clip.to_string()),
+            (Action::Abort, "f9".to_string()),
+        ]).map_err(|e| error::report(&e))
+    }
+    
+    #[test]
+    fn reapplying_the_same_hotkeys_succeeds() {
+        let mut manager = HotkeyManager::with_registry(FakeRegistry::default());
+        apply(&mut manager, "f7", "f8").unwrap();
+        apply(&mut manager, "f7", "f8").unwrap();
+        assert_eq!(registered(&manager).len(), 3);
+    }
+    
+    #[test]
+    fn failed_registration_restores_previous_set() {
+        let mut manager = HotkeyManager::with_registry(FakeRegistry {
+            taken_by_other_app: RefCell::new(vec![HotKey::new(None, Code::F2)]),
+            ..FakeRegistry::default()
+        });
+        apply(&mut manager, "f7", "f8").unwrap();
+        let before = registered(&manager);
+        
+        let error = apply(&mut manager, "f1", "f2").unwrap_err();
+        assert_eq!(error, "Failed to register Wall Clip hotkey 'f2': already registered");
+        assert_eq!(manager.register_hotkeys(&[(Action::Abort, "f2".to_string())]).unwrap_err().action(), Some(&Action::Abort));
+        assert_eq!(registered(&manager), before);
+    }
+    
+    #[test]
+    fn failed_restore_reports_both_errors() {
+        let mut manager = HotkeyManager::with_registry(FakeRegistry {
+            taken_by_other_app: RefCell::new(vec![HotKey::new(None, Code::F2)]),
+            ..FakeRegistry::default()
+        });
+        apply(&mut manager, "f7", "f8").unwrap();
+        // Another app takes F8 as soon as it is let go, so the old set can't come back
+        manager.manager.taken_by_other_app.borrow_mut().push(HotKey::new(None, Code::F8));
+        
+        let error = manager.register_hotkeys(&[
+            (Action::Run(MacroKind::ComOffset), "f1".to_string()),
+            (Action::Run(MacroKind::WallClip), "f2".to_string()),
+        ]).unwrap_err();
+        
+        let HotkeyError::RestoreFailed { error: failed, restore } = &error else {
+            panic!("unexpected {:?}", error);
+        };
+        assert!(matches!(failed.as_ref(), HotkeyError::Register { action: Action::Run(MacroKind::WallClip), .. }));
+        assert!(matches!(restore.as_ref(), HotkeyError::Register { action: Action::Run(MacroKind::WallClip), .. }));
+        assert_eq!(error.action(), Some(&Action::Run(MacroKind::WallClip)));
+        assert_eq!(
+            error.to_string(),
+            "Failed to register Wall Clip hotkey 'f2': already registered; \
+             restoring the previous hotkeys also failed: Failed to register Wall Clip hotkey 'f8': already registered",
+        );
+    }
+    
+    #[test]
+    fn parse_errors_and_duplicates_name_the_binding() {
+        let mut manager = HotkeyManager::with_registry(FakeRegistry::default());
+        apply(&mut manager, "f7", "f8").unwrap();
+        let before = registered(&manager);
+        
+        assert_eq!(apply(&mut manager, "f7", "f99").unwrap_err(), "Wall Clip hotkey: Unsupported key: f99");
+        assert_eq!(apply(&mut manager, "f7", "F7").unwrap_err(), "COM Offset and Wall Clip hotkeys are both 'F7'");
+        
+        let error = manager.register_hotkeys(&[
+            (Action::Abort, "f9".to_string()),
+            (Action::UserMacro("Spin".to_string()), "F9".to_string()),
+        ]).unwrap_err();
+        assert_eq!(error.to_string(), "Abort and Macro 'Spin' hotkeys are both 'F9'");
+        assert_eq!(error.action(), Some(&Action::UserMacro("Spin".to_string())));
+        assert_eq!(registered(&manager), before);
+    }
+    
+    #[test]
+    fn bare_keys_have_no_modifiers() {
+        assert_eq!(parse_hotkey("F7").unwrap(), HotKey::new(None, Code::F7));
+    }
+    
+    #[test]
+    fn modifier_combos_parse_in_any_order() {
+        let expected = HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::F7);
+        assert_eq!(parse_hotkey("ctrl+shift+f7").unwrap(), expected);
+        assert_eq!(parse_hotkey("Shift + Control + F7").unwrap(), expected);
+    }
+    
+    #[test]
+    fn bad_bindings_are_rejected() {
+        assert!(matches!(parse_hotkey("hyper+f7"), Err(HotkeyError::UnknownModifier { modifier, .. }) if modifier == "hyper"));
+        assert!(matches!(parse_hotkey("ctrl+"), Err(HotkeyError::MissingKey(_))));
+        assert!(matches!(parse_hotkey("ctrl+f99"), Err(HotkeyError::UnsupportedKey(_))));
+    }
+    
+    #[test]
+    fn every_capturable_key_parses() {
+        for &key in egui::Key::ALL {
+            if let Ok(binding) = binding_from_egui(egui::Modifiers::CTRL, key, false) {
+                assert!(parse_hotkey(&binding).is_ok(), "{:?} captured as unparsable '{}'", key, binding);
             }
         }
+        assert_eq!(binding_from_egui(egui::Modifiers::NONE, egui::Key::Colon, false).unwrap(), "semicolon");
+        assert_eq!(parse_hotkey("numpad5").unwrap(), HotKey::new(None, Code::Numpad5));
+    }
+    
+    #[test]
+    fn captured_bindings_round_trip() {
+        let modifiers = egui::Modifiers { alt: true, shift: true, ..egui::Modifiers::NONE };
+        let binding = binding_from_egui(modifiers, egui::Key::F8, false).unwrap();
+        assert_eq!(binding, "alt+shift+f8");
+        assert_eq!(parse_hotkey(&binding).unwrap(), HotKey::new(Some(Modifiers::ALT | Modifiers::SHIFT), Code::F8));
+    }
+    
+    #[test]
+    fn logo_key_is_captured_as_super() {
+        // What egui reports for ctrl outside macOS, where `command` follows ctrl
+        let ctrl = egui::Modifiers { ctrl: true, command: true, ..egui::Modifiers::NONE };
+        assert_eq!(binding_from_egui(ctrl, egui::Key::F7, false).unwrap(), "ctrl+f7");
+        assert_eq!(binding_from_egui(ctrl, egui::Key::F7, true).unwrap(), "ctrl+super+f7");
         
-        events
+        let mac_cmd = egui::Modifiers { mac_cmd: true, command: true, ..egui::Modifiers::NONE };
+        let binding = binding_from_egui(mac_cmd, egui::Key::F7, false).unwrap();
+        assert_eq!(binding, "super+f7");
+        assert_eq!(parse_hotkey(&binding).unwrap(), HotKey::new(Some(Modifiers::SUPER), Code::F7));
     }
 }
-
-#[derive(Debug, Clone)]
-pub enum HotkeyEvent {
-    ComOffset,
-    WallClip,
-}
@@ -0,0 +1,141 @@
+// Pluggable input layer so the macros don't care how key and mouse events reach the game
+pub mod enigo_backend;
+#[cfg(test)]
+pub mod recording;
+#[cfg(windows)]
+pub mod sendinput;
+#[cfg(target_os = "linux")]
+pub mod uinput;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use crate::error::InputError;
+
+// Keys are identified by Windows virtual-key codes on every platform,
+// each backend translates them to whatever its OS expects
+pub const VK_LSHIFT: u16 = 0xA0;
+pub const VK_LCONTROL: u16 = 0xA2;
+pub const VK_PERIOD: u16 = 0xBE;
+pub const VK_W: u16 = 0x57;
+
+/// Virtual-key code for a digit key on the top row, e.g. `'5'` -> `0x35`
+pub fn vk_digit(digit: char) -> Option<u16> {
+    digit.is_ascii_digit().then_some(digit as u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl fmt::Display for MouseButton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+            Self::Middle => write!(f, "middle"),
+        }
+    }
+}
+
+pub trait InputBackend: Send {
+    /// Short name shown in the UI and logs
+    fn name(&self) -> &'static str;
+    
+    fn key_down(&mut self, vk_code: u16) -> Result<(), InputError>;
+    
+    fn key_up(&mut self, vk_code: u16) -> Result<(), InputError>;
+    
+    /// Press and release a key, holding it down for `duration`
+    fn press_key(&mut self, vk_code: u16, duration: Duration) -> Result<(), InputError> {
+        self.key_down(vk_code)?;
+        thread::sleep(duration);
+        self.key_up(vk_code)
+    }
+    
+    /// Moves the mouse by `dx`, `dy` device units from where it is, like a physical mouse would
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), InputError>;
+    
+    fn mouse_down(&mut self, button: MouseButton) -> Result<(), InputError>;
+    
+    fn mouse_up(&mut self, button: MouseButton) -> Result<(), InputError>;
+    
+    /// Turns the wheel by `notches`, positive away from the user (up), negative towards
+    fn scroll(&mut self, notches: i32) -> Result<(), InputError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputBackendKind {
+    /// Windows `SendInput` with scan codes
+    SendInput,
+    /// Linux virtual keyboard through `/dev/uinput`
+    Uinput,
+    /// enigo, which uses XTest on X11 and CGEvent on macOS
+    Enigo,
+}
+
+impl Default for InputBackendKind {
+    /// Best backend for the current platform
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::SendInput
+        } else if cfg!(target_os = "linux") {
+            Self::Uinput
+        } else {
+            Self::Enigo
+        }
+    }
+}
+
+impl fmt::Display for InputBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SendInput => write!(f, "SendInput"),
+            Self::Uinput => write!(f, "uinput"),
+            Self::Enigo => write!(f, "enigo"),
+        }
+    }
+}
+
+impl InputBackendKind {
+    pub const ALL: [Self; 3] = [Self::SendInput, Self::Uinput, Self::Enigo];
+    
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::SendInput => cfg!(windows),
+            Self::Uinput => cfg!(target_os = "linux"),
+            Self::Enigo => true,
+        }
+    }
+    
+    /// Whether the backend has a mapping for `vk_code`. Backends this platform lacks say yes,
+    /// they fail with `Unsupported` before sending anything.
+    pub fn can_send(self, vk_code: u16) -> bool {
+        match self {
+            // Windows maps any virtual-key code to its scan code itself
+            Self::SendInput => true,
+            #[cfg(target_os = "linux")]
+            Self::Uinput => uinput::evdev_code(vk_code).is_ok(),
+            Self::Enigo => enigo_backend::vk_to_key(vk_code).is_some(),
+            #[allow(unreachable_patterns)]
+            _ => true,
+        }
+    }
+}
+
+pub fn create_backend(kind: InputBackendKind) -> Result<Box<dyn InputBackend>, InputError> {
+    match kind {
+        #[cfg(windows)]
+        InputBackendKind::SendInput => Ok(Box::new(sendinput::SendInputBackend::new())),
+        #[cfg(target_os = "linux")]
+        InputBackendKind::Uinput => Ok(Box::new(uinput::UinputBackend::new()?)),
+        InputBackendKind::Enigo => Ok(Box::new(enigo_backend::EnigoBackend::new()?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(InputError::Unsupported(kind)),
+    }
+}
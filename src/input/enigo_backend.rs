@@ -0,0 +1,159 @@
+// Cross-platform input through enigo (XTest on X11, CGEvent on macOS)
+use enigo::{Axis, Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+use super::{InputBackend, MouseButton};
+use crate::error::InputError;
+
+pub struct EnigoBackend {
+    enigo: Enigo,
+}
+
+// CGEventSource isn't marked Send, but the backend is only ever used by one thread at a time
+#[cfg(target_os = "macos")]
+unsafe impl Send for EnigoBackend {}
+
+impl EnigoBackend {
+    pub fn new() -> Result<Self, InputError> {
+        let settings = Settings {
+            // The executor does its own timing, don't let enigo pad every event
+            linux_delay: 0,
+            release_keys_when_dropped: true,
+            ..Settings::default()
+        };
+        let enigo = Enigo::new(&settings)
+            .map_err(|e| InputError::Init { backend: "enigo", source: e.into() })?;
+        Ok(Self { enigo })
+    }
+    
+    fn send_key(&mut self, vk_code: u16, direction: Direction) -> Result<(), InputError> {
+        let key = vk_to_key(vk_code)
+            .ok_or(InputError::Unmapped { vk_code, backend: "enigo" })?;
+        self.enigo.key(key, direction)
+            .map_err(|e| send_error(format!("key input for VK {}", vk_code), e))
+    }
+    
+    fn send_button(&mut self, button: MouseButton, direction: Direction) -> Result<(), InputError> {
+        let enigo_button = match button {
+            MouseButton::Left => Button::Left,
+            MouseButton::Right => Button::Right,
+            MouseButton::Middle => Button::Middle,
+        };
+        self.enigo.button(enigo_button, direction)
+            .map_err(|e| send_error(format!("{} mouse button input", button), e))
+    }
+}
+
+fn send_error(what: String, error: enigo::InputError) -> InputError {
+    InputError::Send { what, source: error.into() }
+}
+
+/// Virtual-key code -> enigo key. Characters go through `Key::Unicode`, which enigo
+/// maps to whatever keycode produces them in the active layout.
+pub fn vk_to_key(vk_code: u16) -> Option<Key> {
+    let key = match vk_code {
+        0x30..=0x39 => Key::Unicode(vk_code as u8 as char),
+        0x41..=0x5A => Key::Unicode((vk_code as u8 as char).to_ascii_lowercase()),
+        0x70..=0x7B => [
+            Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+            Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+        ][(vk_code - 0x70) as usize],
+        0x08 => Key::Backspace,
+        0x09 => Key::Tab,
+        0x0D => Key::Return,
+        0x1B => Key::Escape,
+        0x20 => Key::Space,
+        0x25 => Key::LeftArrow,
+        0x26 => Key::UpArrow,
+        0x27 => Key::RightArrow,
+        0x28 => Key::DownArrow,
+        0x10 => Key::Shift,
+        0x11 => Key::Control,
+        0x12 | 0xA4 | 0xA5 => Key::Alt,
+        0xA0 => Key::LShift,
+        0xA1 => Key::RShift,
+        0xA2 => Key::LControl,
+        0xA3 => Key::RControl,
+        0xBA => Key::Unicode(';'),
+        0xBB => Key::Unicode('='),
+        0xBC => Key::Unicode(','),
+        0xBD => Key::Unicode('-'),
+        0xBE => Key::Unicode('.'),
+        0xBF => Key::Unicode('/'),
+        0xC0 => Key::Unicode('`'),
+        0xDB => Key::Unicode('['),
+        0xDC => Key::Unicode('\\'),
+        0xDD => Key::Unicode(']'),
+        0xDE => Key::Unicode('\''),
+        _ => return None,
+    };
+    Some(key)
+}
+
+impl InputBackend for EnigoBackend {
+    fn name(&self) -> &'static str {
+        "enigo"
+    }
+    
+    fn key_down(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.send_key(vk_code, Direction::Press)
+    }
+    
+    fn key_up(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.send_key(vk_code, Direction::Release)
+    }
+    
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), InputError> {
+        self.enigo.move_mouse(dx, dy, Coordinate::Rel)
+            .map_err(|e| send_error("mouse movement".to_string(), e))
+    }
+    
+    fn mouse_down(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.send_button(button, Direction::Press)
+    }
+    
+    fn mouse_up(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.send_button(button, Direction::Release)
+    }
+    
+    fn scroll(&mut self, notches: i32) -> Result<(), InputError> {
+        // enigo scrolls down for positive lengths
+        self.enigo.scroll(-notches, Axis::Vertical)
+            .map_err(|e| send_error("scroll".to_string(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::input::{VK_LCONTROL, VK_LSHIFT, VK_PERIOD, VK_W};
+    use crate::macros::{MacroExecutor, MacroKind};
+    use std::time::Instant;
+    
+    #[test]
+    fn maps_every_key_the_macros_use() {
+        let digits = 0x30..=0x39;
+        for vk in digits.chain([VK_PERIOD, VK_W, VK_LSHIFT, VK_LCONTROL]) {
+            assert!(vk_to_key(vk).is_some(), "VK {:#04x} unmapped", vk);
+        }
+        assert_eq!(vk_to_key(VK_W), Some(Key::Unicode('w')));
+        assert_eq!(vk_to_key(0x75), Some(Key::F6));
+        assert_eq!(vk_to_key(0xFF), None);
+    }
+    
+    /// Runs both macros against a real X server, e.g. `xvfb-run cargo test -- --ignored`
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs an X server on DISPLAY"]
+    fn macros_run_under_x11() {
+        let backend = EnigoBackend::new().expect("X server on DISPLAY should accept XTest input");
+        let mut executor = MacroExecutor::with_backend(Box::new(backend));
+        let config = Config {
+            peak_delay: 0.05,
+            unshiftlock_after: true,
+            ..Config::default()
+        };
+        
+        executor.run_steps("test", &MacroKind::ComOffset.steps(), &config, Instant::now()).unwrap();
+        executor.run_steps("test", &MacroKind::WallClip.steps(), &config, Instant::now()).unwrap();
+    }
+}
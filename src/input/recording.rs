@@ -0,0 +1,126 @@
+// Backend that records input events instead of sending them, used to test macros without a game
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{InputBackend, MouseButton};
+use crate::error::InputError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    KeyDown(u16),
+    KeyUp(u16),
+    MouseMove(i32, i32),
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    Scroll(i32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedEvent {
+    pub event: InputEvent,
+    /// Time since the backend was created
+    pub at: Duration,
+}
+
+/// What the backend does instead of recording a particular event
+#[derive(Debug, Clone, Copy)]
+pub enum Fault {
+    Error,
+    /// Fails the first time only, like a transient driver error
+    ErrorOnce,
+    Panic,
+}
+
+/// Clones share the same log, so keep one clone to inspect what the executor sent
+#[derive(Clone)]
+pub struct RecordingBackend {
+    start: Instant,
+    log: Arc<Mutex<Vec<RecordedEvent>>>,
+    fault: Option<(InputEvent, Fault)>,
+    /// How long each event takes to send, like a slow driver
+    latency: Duration,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            log: Arc::new(Mutex::new(Vec::new())),
+            fault: None,
+            latency: Duration::ZERO,
+        }
+    }
+    
+    /// Makes `event` fail instead of being recorded, to exercise error paths
+    pub fn with_fault(mut self, event: InputEvent, fault: Fault) -> Self {
+        self.fault = Some((event, fault));
+        self
+    }
+    
+    /// Makes every event take `latency` before it is recorded, to exercise timing drift
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+    
+    pub fn recorded(&self) -> Vec<RecordedEvent> {
+        self.log.lock().unwrap().clone()
+    }
+    
+    pub fn events(&self) -> Vec<InputEvent> {
+        self.recorded().iter().map(|r| r.event).collect()
+    }
+    
+    /// Timestamp of the first recorded occurrence of `event`
+    pub fn time_of(&self, event: InputEvent) -> Option<Duration> {
+        self.recorded().iter().find(|r| r.event == event).map(|r| r.at)
+    }
+    
+    fn record(&mut self, event: InputEvent) -> Result<(), InputError> {
+        match self.fault {
+            Some((faulty, fault @ (Fault::Error | Fault::ErrorOnce))) if faulty == event => {
+                if let Fault::ErrorOnce = fault {
+                    self.fault = None;
+                }
+                return Err(InputError::Send { what: format!("{:?}", event), source: "injected failure".into() });
+            }
+            Some((faulty, Fault::Panic)) if faulty == event => panic!("Injected panic on {:?}", event),
+            _ => {}
+        }
+        
+        thread::sleep(self.latency);
+        let at = self.start.elapsed();
+        self.log.lock().unwrap().push(RecordedEvent { event, at });
+        Ok(())
+    }
+}
+
+impl InputBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "Recording"
+    }
+    
+    fn key_down(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.record(InputEvent::KeyDown(vk_code))
+    }
+    
+    fn key_up(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.record(InputEvent::KeyUp(vk_code))
+    }
+    
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), InputError> {
+        self.record(InputEvent::MouseMove(dx, dy))
+    }
+    
+    fn mouse_down(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.record(InputEvent::MouseDown(button))
+    }
+    
+    fn mouse_up(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.record(InputEvent::MouseUp(button))
+    }
+    
+    fn scroll(&mut self, notches: i32) -> Result<(), InputError> {
+        self.record(InputEvent::Scroll(notches))
+    }
+}
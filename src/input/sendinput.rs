@@ -0,0 +1,130 @@
+// Low-level Windows API input simulation for game compatibility
+use winapi::um::winuser::{
+    SendInput, INPUT, INPUT_KEYBOARD, INPUT_MOUSE, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE,
+    MapVirtualKeyW, KEYBDINPUT, MOUSEINPUT, MOUSEEVENTF_MOVE,
+    MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
+    MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_WHEEL, WHEEL_DELTA
+};
+use std::mem;
+use std::io;
+use super::{InputBackend, MouseButton};
+use crate::error::InputError;
+
+/// Like MAPVK_VK_TO_VSC, but keeps the 0xE0 prefix of extended keys in the high byte
+const MAPVK_VK_TO_VSC_EX: u32 = 4;
+
+#[derive(Default)]
+pub struct SendInputBackend;
+
+impl SendInputBackend {
+    pub fn new() -> Self {
+        Self
+    }
+    
+    fn send_key_input(vk_code: u16, key_up: bool) -> Result<(), InputError> {
+        unsafe {
+            let scan_code = MapVirtualKeyW(vk_code as u32, MAPVK_VK_TO_VSC_EX) as u16;
+            // Arrows, Insert/Delete/Home/End, right Ctrl/Alt and the Windows keys share their scan code
+            // with a numpad or left-hand key and are told apart by the prefix
+            let mut flags = KEYEVENTF_SCANCODE;
+            if scan_code & 0xFF00 == 0xE000 {
+                flags |= KEYEVENTF_EXTENDEDKEY;
+            }
+            if key_up {
+                flags |= KEYEVENTF_KEYUP;
+            }
+            let mut input = INPUT {
+                type_: INPUT_KEYBOARD,
+                u: mem::zeroed(),
+            };
+            
+            // Use both virtual key code and scan code for maximum compatibility
+            *input.u.ki_mut() = KEYBDINPUT {
+                wVk: vk_code,
+                wScan: scan_code & 0xFF,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            };
+            
+            let result = SendInput(1, &mut input, mem::size_of::<INPUT>() as i32);
+            if result == 0 {
+                return Err(InputError::Send {
+                    what: format!("key input for VK {}", vk_code),
+                    source: io::Error::last_os_error().into(),
+                });
+            }
+        }
+        Ok(())
+    }
+    
+    fn send_mouse_input(dx: i32, dy: i32, data: i32, flags: u32) -> Result<(), InputError> {
+        unsafe {
+            let mut input = INPUT {
+                type_: INPUT_MOUSE,
+                u: mem::zeroed(),
+            };
+            
+            // Without MOUSEEVENTF_ABSOLUTE the deltas are relative, which is what camera movement reads
+            *input.u.mi_mut() = MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: data as u32,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            };
+            
+            let result = SendInput(1, &mut input, mem::size_of::<INPUT>() as i32);
+            if result == 0 {
+                return Err(InputError::Send {
+                    what: format!("mouse input (flags {:#x})", flags),
+                    source: io::Error::last_os_error().into(),
+                });
+            }
+        }
+        Ok(())
+    }
+    
+    fn button_flags(button: MouseButton, down: bool) -> u32 {
+        match (button, down) {
+            (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+            (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+            (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+            (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+            (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+            (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+        }
+    }
+}
+
+impl InputBackend for SendInputBackend {
+    fn name(&self) -> &'static str {
+        "SendInput"
+    }
+    
+    fn key_down(&mut self, vk_code: u16) -> Result<(), InputError> {
+        Self::send_key_input(vk_code, false)
+    }
+    
+    fn key_up(&mut self, vk_code: u16) -> Result<(), InputError> {
+        Self::send_key_input(vk_code, true)
+    }
+    
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), InputError> {
+        Self::send_mouse_input(dx, dy, 0, MOUSEEVENTF_MOVE)
+    }
+    
+    fn mouse_down(&mut self, button: MouseButton) -> Result<(), InputError> {
+        Self::send_mouse_input(0, 0, 0, Self::button_flags(button, true))
+    }
+    
+    fn mouse_up(&mut self, button: MouseButton) -> Result<(), InputError> {
+        Self::send_mouse_input(0, 0, 0, Self::button_flags(button, false))
+    }
+    
+    fn scroll(&mut self, notches: i32) -> Result<(), InputError> {
+        // Positive wheel data is away from the user, same as ours
+        Self::send_mouse_input(0, 0, notches.saturating_mul(WHEEL_DELTA as i32), MOUSEEVENTF_WHEEL)
+    }
+}
@@ -0,0 +1,387 @@
+// Linux virtual keyboard and mouse through /dev/uinput, works under Wine/Sober and native X11/Wayland alike
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::{slice, thread};
+use std::time::Duration;
+use thiserror::Error;
+use super::{InputBackend, MouseButton};
+use crate::error::InputError;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+pub const DEVICE_NAME: &CStr = c"Moonwalk Macros virtual keyboard";
+
+// From linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BUS_USB: u16 = 0x03;
+
+// From linux/uinput.h, _IO/_IOW('U', nr, ...)
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c_5503;
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_SET_RELBIT: libc::c_ulong = 0x4004_5566;
+
+/// Virtual-key code -> evdev key code (KEY_* in linux/input-event-codes.h)
+const KEY_MAP: &[(u16, u16)] = &[
+    // Digits 0-9
+    (0x30, 11), (0x31, 2), (0x32, 3), (0x33, 4), (0x34, 5),
+    (0x35, 6), (0x36, 7), (0x37, 8), (0x38, 9), (0x39, 10),
+    // Letters A-Z
+    (0x41, 30), (0x42, 48), (0x43, 46), (0x44, 32), (0x45, 18), (0x46, 33), (0x47, 34),
+    (0x48, 35), (0x49, 23), (0x4A, 36), (0x4B, 37), (0x4C, 38), (0x4D, 50), (0x4E, 49),
+    (0x4F, 24), (0x50, 25), (0x51, 16), (0x52, 19), (0x53, 31), (0x54, 20), (0x55, 22),
+    (0x56, 47), (0x57, 17), (0x58, 45), (0x59, 21), (0x5A, 44),
+    // F1-F12
+    (0x70, 59), (0x71, 60), (0x72, 61), (0x73, 62), (0x74, 63), (0x75, 64),
+    (0x76, 65), (0x77, 66), (0x78, 67), (0x79, 68), (0x7A, 87), (0x7B, 88),
+    // Modifiers
+    (0x10, 42), (0x11, 29), (0x12, 56),
+    (0xA0, 42), (0xA1, 54), (0xA2, 29), (0xA3, 97), (0xA4, 56), (0xA5, 100),
+    // Editing and whitespace
+    (0x08, 14), (0x09, 15), (0x0D, 28), (0x1B, 1), (0x20, 57),
+    // Arrows
+    (0x25, 105), (0x26, 103), (0x27, 106), (0x28, 108),
+    // Punctuation (US layout)
+    (0xBA, 39), (0xBB, 13), (0xBC, 51), (0xBD, 12), (0xBE, 52), (0xBF, 53),
+    (0xC0, 41), (0xDB, 26), (0xDC, 43), (0xDD, 27), (0xDE, 40),
+];
+
+pub fn evdev_code(vk_code: u16) -> Result<u16, InputError> {
+    KEY_MAP.iter()
+        .find(|(vk, _)| *vk == vk_code)
+        .map(|(_, code)| *code)
+        .ok_or(InputError::Unmapped { vk_code, backend: "uinput" })
+}
+
+fn button_code(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => BTN_LEFT,
+        MouseButton::Right => BTN_RIGHT,
+        MouseButton::Middle => BTN_MIDDLE,
+    }
+}
+
+/// Why the virtual device couldn't be created
+#[derive(Debug, Error)]
+pub enum DeviceError {
+    #[error("can't open {UINPUT_PATH} (is your user in the input group?)")]
+    Open(#[source] io::Error),
+    #[error("uinput ioctl {request:#x} failed")]
+    Ioctl { request: libc::c_ulong, source: io::Error },
+}
+
+/// The uinput file descriptor, destroys the virtual device when dropped.
+/// The device has the mapped keys plus mouse buttons, relative motion and a wheel.
+pub struct VirtualKeyboard {
+    file: File,
+}
+
+impl VirtualKeyboard {
+    pub fn create() -> Result<Self, DeviceError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)
+            .map_err(DeviceError::Open)?;
+        let fd = file.as_raw_fd();
+        
+        unsafe {
+            ioctl(fd, UI_SET_EVBIT, EV_KEY as libc::c_ulong)?;
+            for (_, code) in KEY_MAP {
+                ioctl(fd, UI_SET_KEYBIT, *code as libc::c_ulong)?;
+            }
+            for code in [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE] {
+                ioctl(fd, UI_SET_KEYBIT, code as libc::c_ulong)?;
+            }
+            ioctl(fd, UI_SET_EVBIT, EV_REL as libc::c_ulong)?;
+            for code in [REL_X, REL_Y, REL_WHEEL] {
+                ioctl(fd, UI_SET_RELBIT, code as libc::c_ulong)?;
+            }
+            
+            let mut setup: libc::uinput_setup = mem::zeroed();
+            setup.id.bustype = BUS_USB;
+            setup.id.vendor = 0x1234;
+            setup.id.product = 0x5678;
+            for (dst, src) in setup.name.iter_mut().zip(DEVICE_NAME.to_bytes()) {
+                *dst = *src as libc::c_char;
+            }
+            ioctl(fd, UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+            ioctl(fd, UI_DEV_CREATE, 0)?;
+        }
+        
+        // Give udev and the compositor a moment to pick up the new device before the first key
+        thread::sleep(Duration::from_millis(100));
+        
+        Ok(Self { file })
+    }
+}
+
+unsafe fn ioctl(fd: libc::c_int, request: libc::c_ulong, arg: libc::c_ulong) -> Result<(), DeviceError> {
+    if libc::ioctl(fd, request as _, arg) < 0 {
+        return Err(DeviceError::Ioctl { request, source: io::Error::last_os_error() });
+    }
+    Ok(())
+}
+
+impl Write for VirtualKeyboard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY as _);
+        }
+    }
+}
+
+/// Writes evdev events to any sink, normally a `VirtualKeyboard`
+pub struct UinputBackend<W: Write + Send = VirtualKeyboard> {
+    device: W,
+}
+
+impl UinputBackend {
+    pub fn new() -> Result<Self, InputError> {
+        let device = VirtualKeyboard::create()
+            .map_err(|e| InputError::Init { backend: "uinput", source: e.into() })?;
+        Ok(Self::with_device(device))
+    }
+}
+
+impl<W: Write + Send> UinputBackend<W> {
+    pub fn with_device(device: W) -> Self {
+        Self { device }
+    }
+    
+    fn send_key(&mut self, vk_code: u16, pressed: bool) -> Result<(), InputError> {
+        let code = evdev_code(vk_code)?;
+        self.send_events(&[input_event(EV_KEY, code, pressed as i32)])
+            .map_err(|e| send_error(format!("key input for VK {}", vk_code), e))
+    }
+    
+    fn send_button(&mut self, button: MouseButton, pressed: bool) -> Result<(), InputError> {
+        self.send_events(&[input_event(EV_KEY, button_code(button), pressed as i32)])
+            .map_err(|e| send_error(format!("{} mouse button input", button), e))
+    }
+    
+    /// Writes `events` as one report, followed by the sync that makes readers act on them
+    fn send_events(&mut self, events: &[libc::input_event]) -> io::Result<()> {
+        let mut report = events.to_vec();
+        report.push(input_event(EV_SYN, SYN_REPORT, 0));
+        
+        let bytes = unsafe {
+            slice::from_raw_parts(report.as_ptr() as *const u8, mem::size_of_val(report.as_slice()))
+        };
+        self.device.write_all(bytes)
+    }
+}
+
+fn send_error(what: String, error: io::Error) -> InputError {
+    InputError::Send { what, source: error.into() }
+}
+
+/// Timestamp is left zeroed, the kernel stamps uinput events itself
+fn input_event(type_: u16, code: u16, value: i32) -> libc::input_event {
+    let mut event: libc::input_event = unsafe { mem::zeroed() };
+    event.type_ = type_;
+    event.code = code;
+    event.value = value;
+    event
+}
+
+impl<W: Write + Send> InputBackend for UinputBackend<W> {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+    
+    fn key_down(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.send_key(vk_code, true)
+    }
+    
+    fn key_up(&mut self, vk_code: u16) -> Result<(), InputError> {
+        self.send_key(vk_code, false)
+    }
+    
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), InputError> {
+        // An axis with no motion is left out, readers may treat a zero event as a real one
+        let events: Vec<_> = [(REL_X, dx), (REL_Y, dy)].into_iter()
+            .filter(|(_, delta)| *delta != 0)
+            .map(|(axis, delta)| input_event(EV_REL, axis, delta))
+            .collect();
+        self.send_events(&events)
+            .map_err(|e| send_error("mouse movement".to_string(), e))
+    }
+    
+    fn mouse_down(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.send_button(button, true)
+    }
+    
+    fn mouse_up(&mut self, button: MouseButton) -> Result<(), InputError> {
+        self.send_button(button, false)
+    }
+    
+    fn scroll(&mut self, notches: i32) -> Result<(), InputError> {
+        // REL_WHEEL is positive away from the user, same as ours
+        self.send_events(&[input_event(EV_REL, REL_WHEEL, notches)])
+            .map_err(|e| send_error("scroll".to_string(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::time::Instant;
+    use crate::config::Config;
+    use crate::macros::{MacroExecutor, MacroKind};
+    
+    const EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
+    
+    /// (type, code, value) triples decoded from raw `input_event` bytes
+    fn decode(bytes: &[u8]) -> Vec<(u16, u16, i32)> {
+        assert_eq!(bytes.len() % EVENT_SIZE, 0);
+        bytes.chunks_exact(EVENT_SIZE)
+            .map(|chunk| {
+                let event: libc::input_event = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const _) };
+                (event.type_, event.code, event.value)
+            })
+            .collect()
+    }
+    
+    fn key(code: u16, value: i32) -> [(u16, u16, i32); 2] {
+        [(EV_KEY, code, value), (EV_SYN, SYN_REPORT, 0)]
+    }
+    
+    fn pipe() -> (File, File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+    
+    #[test]
+    fn key_events_are_followed_by_sync() {
+        let (mut reader, writer) = pipe();
+        let mut backend = UinputBackend::with_device(writer);
+        
+        backend.key_down(0x57).unwrap();
+        backend.key_up(0x57).unwrap();
+        drop(backend);
+        
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(decode(&bytes), [key(17, 1), key(17, 0)].concat());
+    }
+    
+    #[test]
+    fn mouse_events_are_relative_and_synced() {
+        let (mut reader, writer) = pipe();
+        let mut backend = UinputBackend::with_device(writer);
+        
+        backend.mouse_move(-40, 0).unwrap();
+        backend.mouse_move(3, 5).unwrap();
+        backend.mouse_down(MouseButton::Right).unwrap();
+        backend.mouse_up(MouseButton::Right).unwrap();
+        backend.scroll(-2).unwrap();
+        drop(backend);
+        
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        let sync = (EV_SYN, SYN_REPORT, 0);
+        assert_eq!(decode(&bytes), [
+            (EV_REL, REL_X, -40), sync,
+            (EV_REL, REL_X, 3), (EV_REL, REL_Y, 5), sync,
+            (EV_KEY, BTN_RIGHT, 1), sync,
+            (EV_KEY, BTN_RIGHT, 0), sync,
+            (EV_REL, REL_WHEEL, -2), sync,
+        ]);
+    }
+    
+    #[test]
+    fn unmapped_key_is_an_error() {
+        let mut backend = UinputBackend::with_device(Vec::new());
+        assert!(backend.key_down(0xFF).is_err());
+        assert!(backend.device.is_empty());
+    }
+    
+    #[test]
+    fn every_mapped_key_is_unique() {
+        let mut vks: Vec<u16> = KEY_MAP.iter().map(|(vk, _)| *vk).collect();
+        vks.sort();
+        vks.dedup();
+        assert_eq!(vks.len(), KEY_MAP.len());
+    }
+    
+    #[test]
+    fn com_offset_through_pipe() {
+        let (mut reader, writer) = pipe();
+        let mut executor = MacroExecutor::with_backend(Box::new(UinputBackend::with_device(writer)));
+        let config = Config {
+            emote_slot: 3,
+            gear_slot: "0".to_string(),
+            peak_delay: 0.05,
+            ..Config::default()
+        };
+        
+        executor.run_steps("test", &MacroKind::ComOffset.steps(), &config, Instant::now()).unwrap();
+        drop(executor);
+        
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        let tap = |code| [key(code, 1), key(code, 0)].concat();
+        let expected = [tap(52), tap(4), tap(11), tap(11)].concat();
+        assert_eq!(decode(&bytes), expected);
+    }
+    
+    /// Finds the /dev/input/eventN node the kernel created for our virtual keyboard
+    fn find_event_node() -> Option<String> {
+        for entry in fs::read_dir("/sys/class/input").ok()?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with("event") {
+                continue;
+            }
+            let device_name = fs::read_to_string(entry.path().join("device/name")).unwrap_or_default();
+            if device_name.trim_end().as_bytes() == DEVICE_NAME.to_bytes() {
+                return Some(format!("/dev/input/{}", name));
+            }
+        }
+        None
+    }
+    
+    /// Needs write access to /dev/uinput, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs /dev/uinput"]
+    fn events_reach_real_virtual_device() {
+        let mut backend = UinputBackend::new().expect("uinput should be writable (is your user in the input group?)");
+        let node = find_event_node().expect("virtual keyboard event node should appear under /sys/class/input");
+        let mut reader = File::open(&node).expect("virtual keyboard event node should be readable");
+        
+        backend.key_down(0xBE).unwrap();
+        backend.key_up(0xBE).unwrap();
+        
+        let mut bytes = vec![0; EVENT_SIZE * 4];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(decode(&bytes), [key(52, 1), key(52, 0)].concat());
+    }
+}
@@ -0,0 +1,206 @@
+// Canonical key names, shared by everything that stores keys as text
+use eframe::egui::Key;
+use global_hotkey::hotkey::Code;
+use crate::input::{VK_LCONTROL, VK_LSHIFT, VK_PERIOD};
+
+/// Key name -> Windows virtual-key code (the executor's key type on every platform)
+const VK_NAMES: &[(&str, u16)] = &[
+    ("0", 0x30), ("1", 0x31), ("2", 0x32), ("3", 0x33), ("4", 0x34),
+    ("5", 0x35), ("6", 0x36), ("7", 0x37), ("8", 0x38), ("9", 0x39),
+    ("a", 0x41), ("b", 0x42), ("c", 0x43), ("d", 0x44), ("e", 0x45), ("f", 0x46), ("g", 0x47),
+    ("h", 0x48), ("i", 0x49), ("j", 0x4A), ("k", 0x4B), ("l", 0x4C), ("m", 0x4D), ("n", 0x4E),
+    ("o", 0x4F), ("p", 0x50), ("q", 0x51), ("r", 0x52), ("s", 0x53), ("t", 0x54), ("u", 0x55),
+    ("v", 0x56), ("w", 0x57), ("x", 0x58), ("y", 0x59), ("z", 0x5A),
+    ("f1", 0x70), ("f2", 0x71), ("f3", 0x72), ("f4", 0x73), ("f5", 0x74), ("f6", 0x75),
+    ("f7", 0x76), ("f8", 0x77), ("f9", 0x78), ("f10", 0x79), ("f11", 0x7A), ("f12", 0x7B),
+    ("f13", 0x7C), ("f14", 0x7D), ("f15", 0x7E), ("f16", 0x7F), ("f17", 0x80), ("f18", 0x81),
+    ("f19", 0x82), ("f20", 0x83), ("f21", 0x84), ("f22", 0x85), ("f23", 0x86), ("f24", 0x87),
+    ("backspace", 0x08), ("tab", 0x09), ("enter", 0x0D), ("escape", 0x1B), ("space", 0x20),
+    ("arrowleft", 0x25), ("arrowup", 0x26), ("arrowright", 0x27), ("arrowdown", 0x28),
+    ("pageup", 0x21), ("pagedown", 0x22), ("end", 0x23), ("home", 0x24), ("insert", 0x2D), ("delete", 0x2E),
+    ("pause", 0x13), ("capslock", 0x14), ("printscreen", 0x2C), ("numlock", 0x90), ("scrolllock", 0x91),
+    ("numpad0", 0x60), ("numpad1", 0x61), ("numpad2", 0x62), ("numpad3", 0x63), ("numpad4", 0x64),
+    ("numpad5", 0x65), ("numpad6", 0x66), ("numpad7", 0x67), ("numpad8", 0x68), ("numpad9", 0x69),
+    ("numpadmultiply", 0x6A), ("numpadadd", 0x6B), ("numpadsubtract", 0x6D), ("numpaddecimal", 0x6E),
+    ("numpaddivide", 0x6F),
+    ("shift", 0x10), ("ctrl", 0x11), ("alt", 0x12),
+    ("lshift", VK_LSHIFT), ("rshift", 0xA1), ("lctrl", VK_LCONTROL), ("rctrl", 0xA3), ("lalt", 0xA4), ("ralt", 0xA5),
+    ("lwin", 0x5B), ("rwin", 0x5C),
+    ("semicolon", 0xBA), ("equal", 0xBB), ("comma", 0xBC), ("minus", 0xBD), ("period", VK_PERIOD),
+    ("slash", 0xBF), ("backquote", 0xC0), ("bracketleft", 0xDB), ("backslash", 0xDC),
+    ("bracketright", 0xDD), ("quote", 0xDE),
+    ("audiovolumemute", 0xAD), ("audiovolumedown", 0xAE), ("audiovolumeup", 0xAF),
+    ("mediatracknext", 0xB0), ("mediatrackprevious", 0xB1), ("mediastop", 0xB2), ("mediaplaypause", 0xB3),
+];
+
+/// Every key that can be bound to a hotkey: its name, the code it registers as, and the egui keys
+/// the capture UI turns into it. Shifted symbols such as `:` map to the key that types them.
+/// Names match `VK_NAMES` wherever both have the key.
+const HOTKEY_KEYS: &[(&str, Code, &[Key])] = &[
+    ("0", Code::Digit0, &[Key::Num0]), ("1", Code::Digit1, &[Key::Num1]), ("2", Code::Digit2, &[Key::Num2]),
+    ("3", Code::Digit3, &[Key::Num3]), ("4", Code::Digit4, &[Key::Num4]), ("5", Code::Digit5, &[Key::Num5]),
+    ("6", Code::Digit6, &[Key::Num6]), ("7", Code::Digit7, &[Key::Num7]), ("8", Code::Digit8, &[Key::Num8]),
+    ("9", Code::Digit9, &[Key::Num9]),
+    ("a", Code::KeyA, &[Key::A]), ("b", Code::KeyB, &[Key::B]), ("c", Code::KeyC, &[Key::C]),
+    ("d", Code::KeyD, &[Key::D]), ("e", Code::KeyE, &[Key::E]), ("f", Code::KeyF, &[Key::F]),
+    ("g", Code::KeyG, &[Key::G]), ("h", Code::KeyH, &[Key::H]), ("i", Code::KeyI, &[Key::I]),
+    ("j", Code::KeyJ, &[Key::J]), ("k", Code::KeyK, &[Key::K]), ("l", Code::KeyL, &[Key::L]),
+    ("m", Code::KeyM, &[Key::M]), ("n", Code::KeyN, &[Key::N]), ("o", Code::KeyO, &[Key::O]),
+    ("p", Code::KeyP, &[Key::P]), ("q", Code::KeyQ, &[Key::Q]), ("r", Code::KeyR, &[Key::R]),
+    ("s", Code::KeyS, &[Key::S]), ("t", Code::KeyT, &[Key::T]), ("u", Code::KeyU, &[Key::U]),
+    ("v", Code::KeyV, &[Key::V]), ("w", Code::KeyW, &[Key::W]), ("x", Code::KeyX, &[Key::X]),
+    ("y", Code::KeyY, &[Key::Y]), ("z", Code::KeyZ, &[Key::Z]),
+    ("f1", Code::F1, &[Key::F1]), ("f2", Code::F2, &[Key::F2]), ("f3", Code::F3, &[Key::F3]),
+    ("f4", Code::F4, &[Key::F4]), ("f5", Code::F5, &[Key::F5]), ("f6", Code::F6, &[Key::F6]),
+    ("f7", Code::F7, &[Key::F7]), ("f8", Code::F8, &[Key::F8]), ("f9", Code::F9, &[Key::F9]),
+    ("f10", Code::F10, &[Key::F10]), ("f11", Code::F11, &[Key::F11]), ("f12", Code::F12, &[Key::F12]),
+    ("f13", Code::F13, &[Key::F13]), ("f14", Code::F14, &[Key::F14]), ("f15", Code::F15, &[Key::F15]),
+    ("f16", Code::F16, &[Key::F16]), ("f17", Code::F17, &[Key::F17]), ("f18", Code::F18, &[Key::F18]),
+    ("f19", Code::F19, &[Key::F19]), ("f20", Code::F20, &[Key::F20]), ("f21", Code::F21, &[Key::F21]),
+    ("f22", Code::F22, &[Key::F22]), ("f23", Code::F23, &[Key::F23]), ("f24", Code::F24, &[Key::F24]),
+    ("backspace", Code::Backspace, &[Key::Backspace]),
+    ("tab", Code::Tab, &[Key::Tab]),
+    ("enter", Code::Enter, &[Key::Enter]),
+    ("escape", Code::Escape, &[Key::Escape]),
+    ("space", Code::Space, &[Key::Space]),
+    ("arrowleft", Code::ArrowLeft, &[Key::ArrowLeft]),
+    ("arrowup", Code::ArrowUp, &[Key::ArrowUp]),
+    ("arrowright", Code::ArrowRight, &[Key::ArrowRight]),
+    ("arrowdown", Code::ArrowDown, &[Key::ArrowDown]),
+    ("pageup", Code::PageUp, &[Key::PageUp]),
+    ("pagedown", Code::PageDown, &[Key::PageDown]),
+    ("end", Code::End, &[Key::End]),
+    ("home", Code::Home, &[Key::Home]),
+    ("insert", Code::Insert, &[Key::Insert]),
+    ("delete", Code::Delete, &[Key::Delete]),
+    ("pause", Code::Pause, &[]),
+    ("capslock", Code::CapsLock, &[]),
+    ("printscreen", Code::PrintScreen, &[]),
+    ("numlock", Code::NumLock, &[]),
+    ("scrolllock", Code::ScrollLock, &[]),
+    ("semicolon", Code::Semicolon, &[Key::Semicolon, Key::Colon]),
+    ("equal", Code::Equal, &[Key::Equals, Key::Plus]),
+    ("comma", Code::Comma, &[Key::Comma]),
+    ("minus", Code::Minus, &[Key::Minus]),
+    ("period", Code::Period, &[Key::Period]),
+    ("slash", Code::Slash, &[Key::Slash, Key::Questionmark]),
+    ("backquote", Code::Backquote, &[Key::Backtick]),
+    ("bracketleft", Code::BracketLeft, &[Key::OpenBracket]),
+    ("backslash", Code::Backslash, &[Key::Backslash, Key::Pipe]),
+    ("bracketright", Code::BracketRight, &[Key::CloseBracket]),
+    ("quote", Code::Quote, &[Key::Quote]),
+    // egui reports numpad digits as plain digits, so these can only be typed in
+    ("numpad0", Code::Numpad0, &[]), ("numpad1", Code::Numpad1, &[]), ("numpad2", Code::Numpad2, &[]),
+    ("numpad3", Code::Numpad3, &[]), ("numpad4", Code::Numpad4, &[]), ("numpad5", Code::Numpad5, &[]),
+    ("numpad6", Code::Numpad6, &[]), ("numpad7", Code::Numpad7, &[]), ("numpad8", Code::Numpad8, &[]),
+    ("numpad9", Code::Numpad9, &[]),
+    ("numpadmultiply", Code::NumpadMultiply, &[]),
+    ("numpadadd", Code::NumpadAdd, &[]),
+    ("numpadsubtract", Code::NumpadSubtract, &[]),
+    ("numpaddecimal", Code::NumpadDecimal, &[]),
+    ("numpaddivide", Code::NumpadDivide, &[]),
+    ("numpadenter", Code::NumpadEnter, &[]),
+    ("numpadequal", Code::NumpadEqual, &[]),
+    ("audiovolumemute", Code::AudioVolumeMute, &[]),
+    ("audiovolumedown", Code::AudioVolumeDown, &[]),
+    ("audiovolumeup", Code::AudioVolumeUp, &[]),
+    ("mediatracknext", Code::MediaTrackNext, &[]),
+    ("mediatrackprevious", Code::MediaTrackPrevious, &[]),
+    ("mediastop", Code::MediaStop, &[]),
+    ("mediaplaypause", Code::MediaPlayPause, &[]),
+    ("mediaplay", Code::MediaPlay, &[]),
+    ("mediapause", Code::MediaPause, &[]),
+];
+
+pub fn vk_from_name(name: &str) -> Option<u16> {
+    let name = name.to_lowercase();
+    VK_NAMES.iter().find(|(n, _)| *n == name).map(|(_, vk)| *vk)
+}
+
+pub fn vk_name(vk_code: u16) -> Option<&'static str> {
+    VK_NAMES.iter().find(|(_, vk)| *vk == vk_code).map(|(name, _)| *name)
+}
+
+/// Every named key, in table order
+pub fn vk_names() -> impl Iterator<Item = (&'static str, u16)> {
+    VK_NAMES.iter().copied()
+}
+
+/// Reads a key name or a raw virtual-key code written as hex (`"0xbe"`)
+pub fn parse_vk(name: &str) -> Option<u16> {
+    name.strip_prefix("0x")
+        .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+        .or_else(|| vk_from_name(name))
+}
+
+/// The name `parse_vk` reads back: the key's name, or its code in hex if it has none
+pub fn vk_label(vk_code: u16) -> String {
+    vk_name(vk_code).map_or_else(|| format!("{:#04x}", vk_code), str::to_string)
+}
+
+/// Hotkey code for a key name (case-insensitive)
+pub fn hotkey_code(name: &str) -> Option<Code> {
+    let name = name.to_lowercase();
+    HOTKEY_KEYS.iter().find(|(n, _, _)| *n == name).map(|(_, code, _)| *code)
+}
+
+/// Name a key captured in the UI is stored under, or `None` if it can't be a global hotkey
+pub fn egui_key_name(key: Key) -> Option<&'static str> {
+    HOTKEY_KEYS.iter().find(|(_, _, keys)| keys.contains(&key)).map(|(name, _, _)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn names_and_codes_are_unique() {
+        for (i, (name, vk)) in VK_NAMES.iter().enumerate() {
+            assert!(VK_NAMES[i + 1..].iter().all(|(n, v)| n != name && v != vk), "{} duplicated", name);
+        }
+    }
+    
+    #[test]
+    fn lookup_round_trips() {
+        assert_eq!(vk_from_name("W"), Some(0x57));
+        assert_eq!(vk_from_name("period"), Some(VK_PERIOD));
+        assert_eq!(vk_name(VK_LSHIFT), Some("lshift"));
+        assert_eq!(vk_from_name("nope"), None);
+    }
+    
+    #[test]
+    fn hotkey_names_codes_and_egui_keys_are_unique() {
+        for (i, (name, code, keys)) in HOTKEY_KEYS.iter().enumerate() {
+            for (other_name, other_code, other_keys) in &HOTKEY_KEYS[i + 1..] {
+                assert!(name != other_name && code != other_code, "{} duplicated", name);
+                assert!(keys.iter().all(|k| !other_keys.contains(k)), "{} shares an egui key", name);
+            }
+        }
+    }
+    
+    #[test]
+    fn every_egui_key_maps_to_a_hotkey_code() {
+        // Not registrable by global-hotkey on any platform
+        let unsupported = [
+            Key::Copy, Key::Cut, Key::Paste,
+            Key::F25, Key::F26, Key::F27, Key::F28, Key::F29, Key::F30,
+            Key::F31, Key::F32, Key::F33, Key::F34, Key::F35,
+        ];
+        
+        for &key in Key::ALL {
+            match egui_key_name(key) {
+                Some(name) => assert!(hotkey_code(name).is_some(), "{:?} -> {}", key, name),
+                None => assert!(unsupported.contains(&key), "{:?} has no hotkey name", key),
+            }
+        }
+    }
+    
+    #[test]
+    fn hotkey_names_agree_with_virtual_key_names() {
+        for (name, _, _) in HOTKEY_KEYS {
+            let shared = VK_NAMES.iter().any(|(n, _)| n == name);
+            let hotkey_only = ["numpadenter", "numpadequal", "mediaplay", "mediapause"];
+            assert!(shared || hotkey_only.contains(name), "{} missing from VK_NAMES", name);
+        }
+    }
+}
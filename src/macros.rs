@@ -1,208 +1,847 @@
-// Low-level Windows API input simulation for game compatibility
-#[cfg(windows)]
-use winapi::um::winuser::{
-    SendInput, INPUT, INPUT_KEYBOARD, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE,
-    VK_LSHIFT, VK_LCONTROL, MapVirtualKeyW, MAPVK_VK_TO_VSC
-};
-#[cfg(windows)]
-use winapi::um::winuser::KEYBDINPUT;
-#[cfg(windows)]
-use std::mem;
-
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
-use crate::config::{Config, ShiftlockKey};
+use std::time::{Duration, Instant};
+use crate::config::{Config, GameKeymap, MovementKey};
+use crate::error::{self, MacroError};
+use crate::input::{InputBackend, MouseButton};
+use crate::steps::{self, MacroStep};
+use crate::timer::PrecisionTimer;
+
+/// Longest stretch a wait sleeps before checking for cancellation again
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MacroKind {
+    ComOffset,
+    WallClip,
+}
+
+impl MacroKind {
+    pub fn steps(self) -> Vec<MacroStep> {
+        match self {
+            Self::ComOffset => steps::com_offset(),
+            Self::WallClip => steps::wall_clip(),
+        }
+    }
+}
+
+impl fmt::Display for MacroKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ComOffset => write!(f, "COM Offset"),
+            Self::WallClip => write!(f, "Wall Clip"),
+        }
+    }
+}
+
+pub type ProgressCallback = Box<dyn Fn(&str) + Send>;
+
+/// How far behind its deadline one input step of the last run was sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepTiming {
+    /// What the step did, such as "press gear slot 1"
+    pub step: String,
+    /// When the step was due, measured from the trigger
+    pub due: Duration,
+    pub late: Duration,
+}
+
+/// Fixed timeline for one run. Every step is due at an offset from the trigger, so time a slow
+/// key press loses is made up by the next wait instead of pushing back everything after it.
+struct Schedule {
+    origin: Instant,
+    /// Offset of the next step
+    cursor: Duration,
+}
+
+impl Schedule {
+    fn deadline(&self) -> Instant {
+        self.origin + self.cursor
+    }
+}
+
+/// Shared flag to stop a running macro, clones refer to the same flag
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
 
 pub struct MacroExecutor {
-    // Empty struct - we'll use static Windows API calls
+    backend: Box<dyn InputBackend>,
+    on_progress: Option<ProgressCallback>,
+    cancel: CancelToken,
+    /// Keys that are, or might be, down because of us, in press order
+    held_keys: Vec<u16>,
+    held_buttons: Vec<MouseButton>,
+    /// When the current or last macro handed its first key to the backend
+    first_input_at: Option<Instant>,
+    schedule: Schedule,
+    timings: Vec<StepTiming>,
+    timer: PrecisionTimer,
+}
+
+/// Releases every key the executor still holds when dropped, including while unwinding from a panic
+struct HeldKeyGuard<'a> {
+    executor: &'a mut MacroExecutor,
+}
+
+impl Deref for HeldKeyGuard<'_> {
+    type Target = MacroExecutor;
+    
+    fn deref(&self) -> &MacroExecutor {
+        self.executor
+    }
+}
+
+impl DerefMut for HeldKeyGuard<'_> {
+    fn deref_mut(&mut self) -> &mut MacroExecutor {
+        self.executor
+    }
+}
+
+impl Drop for HeldKeyGuard<'_> {
+    fn drop(&mut self) {
+        self.executor.release_held_keys();
+    }
 }
 
 impl MacroExecutor {
-    pub fn new() -> Result<Self, String> {
-        Ok(Self {})
-    }
-    
-    #[cfg(windows)]
-    fn send_key_input(vk_code: u16, scan_code: u16, key_up: bool) -> Result<(), String> {
-        unsafe {
-            let mut input = INPUT {
-                type_: INPUT_KEYBOARD,
-                u: mem::zeroed(),
-            };
-            
-            // Use both virtual key code and scan code for maximum compatibility
-            *input.u.ki_mut() = KEYBDINPUT {
-                wVk: vk_code,
-                wScan: scan_code,
-                dwFlags: if key_up { KEYEVENTF_KEYUP | KEYEVENTF_SCANCODE } else { KEYEVENTF_SCANCODE },
-                time: 0,
-                dwExtraInfo: 0,
-            };
-            
-            let result = SendInput(1, &mut input, mem::size_of::<INPUT>() as i32);
-            if result == 0 {
-                return Err(format!("Failed to send key input for VK {}", vk_code));
-            }
+    pub fn with_backend(backend: Box<dyn InputBackend>) -> Self {
+        Self {
+            backend,
+            on_progress: None,
+            cancel: CancelToken::default(),
+            held_keys: Vec::new(),
+            held_buttons: Vec::new(),
+            first_input_at: None,
+            schedule: Schedule { origin: Instant::now(), cursor: Duration::ZERO },
+            timings: Vec::new(),
+            timer: PrecisionTimer::new(Duration::ZERO),
         }
-        Ok(())
     }
     
-    #[cfg(windows)]
-    fn press_key(vk_code: u16) -> Result<(), String> {
-        unsafe {
-            let scan_code = MapVirtualKeyW(vk_code as u32, MAPVK_VK_TO_VSC) as u16;
-            Self::send_key_input(vk_code, scan_code, false)?; // Press
-            thread::sleep(Duration::from_millis(10));
-            Self::send_key_input(vk_code, scan_code, true)?;  // Release
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+    
+    /// Called with a short description as each step of a macro starts. It runs inside the timed
+    /// sequence, so it should hand the message off (e.g. over a channel) rather than block.
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.on_progress = Some(callback);
+    }
+    
+    fn progress(&self, message: &str) {
+        if let Some(callback) = &self.on_progress {
+            callback(message);
         }
-        Ok(())
     }
     
-    #[cfg(windows)]
-    fn hold_key(vk_code: u16) -> Result<(), String> {
-        unsafe {
-            let scan_code = MapVirtualKeyW(vk_code as u32, MAPVK_VK_TO_VSC) as u16;
-            Self::send_key_input(vk_code, scan_code, false) // Just press, don't release
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+    
+    /// When the last run sent its first key, for measuring trigger-to-input latency
+    pub fn first_input_at(&self) -> Option<Instant> {
+        self.first_input_at
+    }
+    
+    /// Lateness of every input step of the last run, in order
+    pub fn timings(&self) -> &[StepTiming] {
+        &self.timings
+    }
+    
+    fn mark_input(&mut self) {
+        self.first_input_at.get_or_insert_with(Instant::now);
+    }
+    
+    /// Fails with `MacroError::Cancelled` once the cancel token has been triggered
+    fn checkpoint(&self) -> Result<(), MacroError> {
+        if self.cancel.is_cancelled() {
+            return Err(MacroError::Cancelled);
         }
+        Ok(())
     }
     
-    #[cfg(windows)]
-    fn release_key(vk_code: u16) -> Result<(), String> {
-        unsafe {
-            let scan_code = MapVirtualKeyW(vk_code as u32, MAPVK_VK_TO_VSC) as u16;
-            Self::send_key_input(vk_code, scan_code, true) // Just release
+    /// Sleeps until `deadline`, waking up early if the macro gets cancelled
+    fn wait_until(&self, deadline: Instant) -> Result<(), MacroError> {
+        loop {
+            self.checkpoint()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining <= CANCEL_POLL_INTERVAL + self.timer.spin_threshold() {
+                // The last stretch goes to the precise timer in one go
+                self.timer.sleep_until(deadline);
+                return self.checkpoint();
+            }
+            thread::sleep(CANCEL_POLL_INTERVAL);
         }
     }
     
-    #[cfg(not(windows))]
-    fn press_key(_vk_code: u16) -> Result<(), String> {
-        Err("Windows-only functionality".to_string())
+    fn press_key(&mut self, vk_code: u16, duration: Duration) -> Result<(), MacroError> {
+        self.checkpoint()?;
+        // Tracked up front since a failure between down and up would otherwise go unnoticed
+        self.held_keys.push(vk_code);
+        self.mark_input();
+        self.backend.press_key(vk_code, duration)?;
+        self.held_keys.pop();
+        Ok(())
     }
     
-    #[cfg(not(windows))]
-    fn hold_key(_vk_code: u16) -> Result<(), String> {
-        Err("Windows-only functionality".to_string())
+    fn hold_key(&mut self, vk_code: u16) -> Result<(), MacroError> {
+        self.checkpoint()?;
+        self.mark_input();
+        self.backend.key_down(vk_code)?; // Just press, don't release
+        self.held_keys.push(vk_code);
+        Ok(())
     }
     
-    #[cfg(not(windows))]
-    fn release_key(_vk_code: u16) -> Result<(), String> {
-        Err("Windows-only functionality".to_string())
+    /// Stops tracking the key only once it is released, so a failed release is retried by the guard
+    fn release_key(&mut self, vk_code: u16) -> Result<(), MacroError> {
+        self.backend.key_up(vk_code)?; // Just release
+        self.held_keys.retain(|&held| held != vk_code);
+        Ok(())
     }
     
-    pub fn execute_com_offset(&mut self, config: &Config) -> Result<(), String> {
-        // Validate configuration
-        config.validate_all()?;
-        
-        println!("Starting COM Offset macro...");
-        
-        #[cfg(windows)]
-        {
-            // Send "." key using low-level input simulation
-            println!("Sending '.' (Period)...");
-            Self::press_key(0xBE)?; // Press and release Period directly
-            thread::sleep(Duration::from_millis(50));
-            
-            // Send emote slot number
-            let emote_vk = match config.emote_slot {
-                1 => 0x31, 2 => 0x32, 3 => 0x33, 4 => 0x34,
-                5 => 0x35, 6 => 0x36, 7 => 0x37, 8 => 0x38,
-                _ => return Err("Invalid emote slot".to_string()),
-            };
-            
-            println!("Sending emote slot {}...", config.emote_slot);
-            Self::press_key(emote_vk)?;
-            thread::sleep(Duration::from_millis(50));
-            
-            // Wait for peak delay
-            let delay_ms = (config.peak_delay * 1000.0) as u64;
-            println!("Waiting {} ms for peak delay...", delay_ms);
-            thread::sleep(Duration::from_millis(delay_ms));
-            
-            // Send gear slot
-            let gear_char = config.gear_slot.chars().next().unwrap_or('1');
-            let gear_vk = match gear_char {
-                '1' => 0x31, '2' => 0x32, '3' => 0x33, '4' => 0x34, '5' => 0x35,
-                '6' => 0x36, '7' => 0x37, '8' => 0x38, '9' => 0x39, '0' => 0x30,
-                _ => return Err("Invalid gear slot".to_string()),
-            };
-            
-            println!("Sending gear slot {}...", gear_char);
-            Self::press_key(gear_vk)?;
-            
-            // Unequip if enabled
-            if config.unequip_after {
-                thread::sleep(Duration::from_millis(100));
-                println!("Unequipping gear...");
-                Self::press_key(gear_vk)?;
+    fn hold_button(&mut self, button: MouseButton) -> Result<(), MacroError> {
+        self.checkpoint()?;
+        self.mark_input();
+        self.backend.mouse_down(button)?;
+        self.held_buttons.push(button);
+        Ok(())
+    }
+    
+    fn release_button(&mut self, button: MouseButton) -> Result<(), MacroError> {
+        self.backend.mouse_up(button)?;
+        self.held_buttons.retain(|&held| held != button);
+        Ok(())
+    }
+    
+    /// Releases everything still held, newest first, so a cancelled or failed macro doesn't leave keys stuck down
+    fn release_held_keys(&mut self) {
+        while let Some(button) = self.held_buttons.pop() {
+            if let Err(e) = self.backend.mouse_up(button) {
+                eprintln!("Failed to release {} mouse button: {}", button, error::report(&e));
             }
-            
-            println!("COM Offset macro completed!");
         }
-        
-        #[cfg(not(windows))]
-        {
-            return Err("Macro execution only supported on Windows".to_string());
+        while let Some(vk_code) = self.held_keys.pop() {
+            if let Err(e) = self.backend.key_up(vk_code) {
+                eprintln!("Failed to release VK {:#04x}: {}", vk_code, error::report(&e));
+            }
         }
-        
+    }
+    
+    /// Recovery for keys the game still thinks are down: releases everything we hold
+    /// plus every key `keymap` lets a macro hold, whether or not we think it is down
+    pub fn release_all_keys(&mut self, keymap: &GameKeymap) -> Result<(), MacroError> {
+        self.release_held_keys();
+        // One stuck key must not keep the rest held, so try them all and report the first failure
+        let mut first_error = None;
+        for vk_code in keymap.holdable_keys() {
+            if let Err(e) = self.backend.key_up(vk_code) {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), |e| Err(e.into()))
+    }
+    
+    /// Waits for the next step's deadline, then notes how late it is about to be sent
+    fn start_input_step(&mut self, step: String) -> Result<(), MacroError> {
+        let deadline = self.schedule.deadline();
+        self.wait_until(deadline)?;
+        self.timings.push(StepTiming {
+            step,
+            due: self.schedule.cursor,
+            late: Instant::now().saturating_duration_since(deadline),
+        });
         Ok(())
     }
     
-    pub fn execute_wall_clip(&mut self, config: &Config) -> Result<(), String> {
+    /// Runs a step list from start to finish, releasing anything still held however it ends.
+    /// Step deadlines are measured from `origin`, normally the moment of the trigger.
+    pub fn run_steps(&mut self, name: &str, steps: &[MacroStep], config: &Config, origin: Instant) -> Result<(), MacroError> {
         // Validate configuration
         config.validate_all()?;
         
-        #[cfg(windows)]
-        {
-            // Send "." key using low-level input simulation
-            Self::press_key(0xBE)?; // Press and release Period directly
-            thread::sleep(Duration::from_millis(50));
-            
-            // Send emote slot number
-            let emote_vk = match config.emote_slot {
-                1 => 0x31, 2 => 0x32, 3 => 0x33, 4 => 0x34,
-                5 => 0x35, 6 => 0x36, 7 => 0x37, 8 => 0x38,
-                _ => return Err("Invalid emote slot".to_string()),
-            };
-            
-            Self::press_key(emote_vk)?;
-            thread::sleep(Duration::from_millis(50));
-            
-            // Wait for peak delay
-            let delay_ms = (config.peak_delay * 1000.0) as u64;
-            thread::sleep(Duration::from_millis(delay_ms));
-            
-            // Get shiftlock key
-            let shift_vk = match config.shiftlock_key {
-                ShiftlockKey::Shift => VK_LSHIFT as u16,
-                ShiftlockKey::Control => VK_LCONTROL as u16,
-            };
-            
-            // Hold shiftlock + W for movement
-            Self::hold_key(shift_vk)?; // Press and hold shiftlock
-            thread::sleep(Duration::from_millis(20));
-            
-            Self::hold_key(0x57)?; // Press and hold W
-            
-            // Hold both keys for 0.25 seconds
-            thread::sleep(Duration::from_millis(250));
-            
-            // Release keys in reverse order
-            Self::release_key(0x57)?; // Release W
-            thread::sleep(Duration::from_millis(20));
-            Self::release_key(shift_vk)?; // Release shiftlock
-            
-            // Unshiftlock if enabled
-            if config.unshiftlock_after {
-                thread::sleep(Duration::from_millis(100));
-                Self::press_key(shift_vk)?; // Quick press to toggle shiftlock off
+        self.first_input_at = None;
+        self.schedule = Schedule { origin, cursor: Duration::ZERO };
+        self.timings.clear();
+        self.timer = PrecisionTimer::new(config.spin_threshold());
+        self.progress(&format!("Starting {} macro...", name));
+        HeldKeyGuard { executor: self }.run_step_list(steps, config)?;
+        self.progress(&format!("{} macro completed!", name));
+        Ok(())
+    }
+    
+    fn run_step_list(&mut self, steps: &[MacroStep], config: &Config) -> Result<(), MacroError> {
+        for step in steps {
+            match step {
+                MacroStep::KeyPress { key } => {
+                    let vk_code = key.resolve(config)?;
+                    // Reported ahead of the deadline so it doesn't delay the key
+                    self.progress(&format!("Pressing {}...", key.describe(config)));
+                    self.start_input_step(format!("press {}", key.describe(config)))?;
+                    self.press_key(vk_code, config.timing.key_press())?;
+                    self.schedule.cursor += config.timing.key_press();
+                }
+                MacroStep::KeyDown { key } => {
+                    let vk_code = key.resolve(config)?;
+                    self.progress(&format!("Holding {}...", key.describe(config)));
+                    self.start_input_step(format!("hold {}", key.describe(config)))?;
+                    self.hold_key(vk_code)?;
+                }
+                MacroStep::KeyUp { key } => {
+                    let vk_code = key.resolve(config)?;
+                    self.start_input_step(format!("release {}", key.describe(config)))?;
+                    self.release_key(vk_code)?;
+                }
+                MacroStep::MouseMove { dx, dy } => {
+                    self.start_input_step(format!("move mouse {:+} {:+}", dx, dy))?;
+                    self.checkpoint()?;
+                    self.mark_input();
+                    self.backend.mouse_move(*dx, *dy)?;
+                }
+                MacroStep::MouseDown { button } => {
+                    self.progress(&format!("Holding {} mouse button...", button));
+                    self.start_input_step(format!("hold {} mouse", button))?;
+                    self.hold_button(*button)?;
+                }
+                MacroStep::MouseUp { button } => {
+                    self.start_input_step(format!("release {} mouse", button))?;
+                    self.release_button(*button)?;
+                }
+                MacroStep::Scroll { notches } => {
+                    self.start_input_step(format!("scroll {:+}", notches))?;
+                    self.checkpoint()?;
+                    self.mark_input();
+                    self.backend.scroll(*notches)?;
+                }
+                MacroStep::Wait { ms } => {
+                    self.schedule.cursor += Duration::from_millis(*ms);
+                    self.wait_until(self.schedule.deadline())?;
+                }
+                MacroStep::WaitPeakDelay => {
+                    // The peak is `peak_delay` after the first input, whatever the steps before it took
+                    let delay_ms = (config.peak_delay * 1000.0).round() as u64;
+                    self.progress(&format!("Waiting until {} ms for peak delay...", delay_ms));
+                    self.schedule.cursor = self.schedule.cursor.max(Duration::from_millis(delay_ms));
+                    self.wait_until(self.schedule.deadline())?;
+                }
+                MacroStep::WaitDelay { delay } => {
+                    self.schedule.cursor += delay.duration(config);
+                    self.wait_until(self.schedule.deadline())?;
+                }
+                MacroStep::WallClipMovement => self.run_movement(&config.wall_clip_movement, config)?,
+                MacroStep::Conditional { flag, steps } => {
+                    if flag.is_set(config) {
+                        self.run_step_list(steps, config)?;
+                    }
+                }
             }
         }
+        Ok(())
+    }
+    
+    /// Holds every key of `movement` over its own window, the step ends when the last one is released
+    fn run_movement(&mut self, movement: &[MovementKey], config: &Config) -> Result<(), MacroError> {
+        let base = self.schedule.cursor;
+        let names: Vec<String> = movement.iter().map(|m| m.key.describe(config)).collect();
+        self.progress(&format!("Moving {}...", names.join("+")));
         
-        #[cfg(not(windows))]
-        {
-            return Err("Macro execution only supported on Windows".to_string());
+        // (offset, is_down, key); releases sort before presses at the same offset
+        let mut events = Vec::with_capacity(movement.len() * 2);
+        for m in movement {
+            let vk_code = m.key.resolve(config)?;
+            events.push((m.start_ms, true, vk_code, m.key));
+            events.push((m.start_ms + m.hold_ms, false, vk_code, m.key));
         }
+        events.sort_by_key(|&(offset, is_down, _, _)| (offset, is_down));
         
+        for (offset, is_down, vk_code, key) in events {
+            self.schedule.cursor = base + Duration::from_millis(offset);
+            if is_down {
+                self.start_input_step(format!("hold {}", key.describe(config)))?;
+                self.hold_key(vk_code)?;
+            } else {
+                self.start_input_step(format!("release {}", key.describe(config)))?;
+                self.release_key(vk_code)?;
+            }
+        }
         Ok(())
     }
 }
+
+impl Drop for MacroExecutor {
+    fn drop(&mut self) {
+        self.release_held_keys();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StepTimings;
+    use crate::error::{ConfigField, InputError};
+    use crate::input::{VK_LCONTROL, VK_LSHIFT, VK_PERIOD, VK_W};
+    use crate::input::recording::{Fault, InputEvent::{self, KeyDown, KeyUp, MouseDown, MouseMove, MouseUp, Scroll}, RecordingBackend};
+    use crate::steps::KeyRef;
+    use std::panic::{self, AssertUnwindSafe};
+    
+    // Smallest delay validate_delay accepts, keeps the suite fast
+    const FAST_PEAK_DELAY: f64 = 0.05;
+    
+    fn fast_config() -> Config {
+        Config {
+            peak_delay: FAST_PEAK_DELAY,
+            ..Config::default()
+        }
+    }
+    
+    fn execute(executor: &mut MacroExecutor, kind: MacroKind, config: &Config) -> Result<(), MacroError> {
+        executor.run_steps(&kind.to_string(), &kind.steps(), config, Instant::now())
+    }
+    
+    fn executor() -> (MacroExecutor, RecordingBackend) {
+        let recorder = RecordingBackend::new();
+        (MacroExecutor::with_backend(Box::new(recorder.clone())), recorder)
+    }
+    
+    fn tap(vk: u16) -> [InputEvent; 2] {
+        [KeyDown(vk), KeyUp(vk)]
+    }
+    
+    fn emote_prefix(emote_vk: u16) -> Vec<InputEvent> {
+        [tap(VK_PERIOD), tap(emote_vk)].concat()
+    }
+    
+    #[test]
+    fn com_offset_with_unequip() {
+        let (mut executor, recorder) = executor();
+        let config = fast_config();
+        
+        execute(&mut executor, MacroKind::ComOffset, &config).unwrap();
+        
+        let expected = [emote_prefix(0x31), tap(0x31).to_vec(), tap(0x31).to_vec()].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn com_offset_without_unequip() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            emote_slot: 4,
+            gear_slot: "7".to_string(),
+            unequip_after: false,
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::ComOffset, &config).unwrap();
+        
+        let expected = [emote_prefix(0x34), tap(0x37).to_vec()].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn com_offset_gear_slot_zero() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            emote_slot: 8,
+            gear_slot: "0".to_string(),
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::ComOffset, &config).unwrap();
+        
+        let expected = [emote_prefix(0x38), tap(0x30).to_vec(), tap(0x30).to_vec()].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn com_offset_waits_for_peak_delay() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            peak_delay: 0.2,
+            unequip_after: false,
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::ComOffset, &config).unwrap();
+        
+        // The gear is due 200 ms after the emote menu opened, the presses and gaps before it included
+        let menu_down = recorder.time_of(KeyDown(VK_PERIOD)).unwrap();
+        let gear_down = recorder.recorded().last().map(|r| r.at).unwrap();
+        assert!(gear_down - menu_down >= Duration::from_millis(200));
+        assert_eq!(executor.timings().last().map(|t| t.due), Some(Duration::from_millis(200)));
+    }
+    
+    #[test]
+    fn peak_delay_never_moves_the_schedule_back() {
+        let (mut executor, _recorder) = executor();
+        let steps = [
+            MacroStep::Wait { ms: 80 },
+            MacroStep::WaitPeakDelay,
+            MacroStep::KeyPress { key: KeyRef::GearSlot },
+        ];
+        let config = Config { peak_delay: FAST_PEAK_DELAY, ..fast_config() };
+        
+        executor.run_steps("test", &steps, &config, Instant::now()).unwrap();
+        assert_eq!(executor.timings()[0].due, Duration::from_millis(80));
+    }
+    
+    #[test]
+    fn wall_clip_with_shift() {
+        let (mut executor, recorder) = executor();
+        let config = fast_config();
+        
+        execute(&mut executor, MacroKind::WallClip, &config).unwrap();
+        
+        let expected = [
+            emote_prefix(0x31),
+            vec![KeyDown(VK_LSHIFT), KeyDown(VK_W), KeyUp(VK_W), KeyUp(VK_LSHIFT)],
+        ].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn wall_clip_with_control_and_unshiftlock() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            emote_slot: 2,
+            keymap: GameKeymap { shiftlock: VK_LCONTROL, ..GameKeymap::default() },
+            unshiftlock_after: true,
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::WallClip, &config).unwrap();
+        
+        let expected = [
+            emote_prefix(0x32),
+            vec![KeyDown(VK_LCONTROL), KeyDown(VK_W), KeyUp(VK_W), KeyUp(VK_LCONTROL)],
+            tap(VK_LCONTROL).to_vec(),
+        ].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn wall_clip_holds_w_for_quarter_second() {
+        let (mut executor, recorder) = executor();
+        
+        execute(&mut executor, MacroKind::WallClip, &fast_config()).unwrap();
+        
+        let w_down = recorder.time_of(KeyDown(VK_W)).unwrap();
+        let w_up = recorder.time_of(KeyUp(VK_W)).unwrap();
+        // W up is due 250 ms after W down was due, so any lateness of the down shortens the hold
+        let w_down_late = executor.timings().iter().find(|t| t.step == "hold forward").unwrap().late;
+        assert!(w_up - w_down + w_down_late >= Duration::from_millis(250));
+    }
+    
+    #[test]
+    fn wall_clip_uses_configured_timings() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            timing: StepTimings { key_press_ms: 30, ..StepTimings::default() },
+            wall_clip_movement: vec![MovementKey { key: KeyRef::MoveForward, start_ms: 0, hold_ms: 60 }],
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::WallClip, &config).unwrap();
+        
+        let period_held = recorder.time_of(KeyUp(VK_PERIOD)).unwrap() - recorder.time_of(KeyDown(VK_PERIOD)).unwrap();
+        let w_held = recorder.time_of(KeyUp(VK_W)).unwrap() - recorder.time_of(KeyDown(VK_W)).unwrap();
+        assert!(period_held >= Duration::from_millis(30));
+        assert!(w_held < Duration::from_millis(250), "{:?}", w_held);
+        let dues: Vec<u128> = executor.timings().iter().map(|t| t.due.as_millis()).collect();
+        // Two 30 ms presses and two 50 ms gaps (outlasting the 50 ms peak delay), then 20 ms gaps around the 60 ms hold
+        assert_eq!(dues, [0, 80, 160, 180, 240, 260]);
+    }
+    
+    #[test]
+    fn wall_clip_staggers_diagonal_movement() {
+        let (mut executor, recorder) = executor();
+        let config = Config {
+            wall_clip_movement: vec![
+                MovementKey { key: KeyRef::MoveForward, start_ms: 0, hold_ms: 100 },
+                MovementKey { key: KeyRef::MoveLeft, start_ms: 30, hold_ms: 40 },
+            ],
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::WallClip, &config).unwrap();
+        
+        let expected = [
+            emote_prefix(0x31),
+            vec![KeyDown(VK_LSHIFT), KeyDown(VK_W), KeyDown(0x41), KeyUp(0x41), KeyUp(VK_W), KeyUp(VK_LSHIFT)],
+        ].concat();
+        assert_eq!(recorder.events(), expected);
+        let movement: Vec<(&str, u128)> = executor.timings()[2..7].iter()
+            .map(|t| (t.step.as_str(), t.due.as_millis()))
+            .collect();
+        // Movement starts 20 ms after shiftlock at 120 ms; shiftlock lifts 20 ms after the last key
+        assert_eq!(movement, [
+            ("hold shiftlock", 120),
+            ("hold forward", 140),
+            ("hold left", 170),
+            ("release left", 210),
+            ("release forward", 240),
+        ]);
+        assert_eq!(executor.timings()[7].due.as_millis(), 260);
+    }
+    
+    #[test]
+    fn mouse_steps_reach_the_backend_on_schedule() {
+        let (mut executor, recorder) = executor();
+        let steps = [
+            MacroStep::MouseDown { button: MouseButton::Right },
+            MacroStep::MouseMove { dx: -300, dy: 0 },
+            MacroStep::Wait { ms: 30 },
+            MacroStep::MouseUp { button: MouseButton::Right },
+            MacroStep::Scroll { notches: 2 },
+        ];
+        
+        executor.run_steps("Flick", &steps, &fast_config(), Instant::now()).unwrap();
+        
+        assert_eq!(recorder.events(), [
+            MouseDown(MouseButton::Right),
+            MouseMove(-300, 0),
+            MouseUp(MouseButton::Right),
+            Scroll(2),
+        ]);
+        let timings: Vec<(&str, u128)> = executor.timings().iter().map(|t| (t.step.as_str(), t.due.as_millis())).collect();
+        assert_eq!(timings, [
+            ("hold right mouse", 0),
+            ("move mouse -300 +0", 0),
+            ("release right mouse", 30),
+            ("scroll +2", 30),
+        ]);
+    }
+    
+    #[test]
+    fn cancelled_macro_releases_held_mouse_buttons() {
+        let (mut executor, recorder) = executor();
+        let cancel = CancelToken::default();
+        executor.set_cancel_token(cancel.clone());
+        let steps = [
+            MacroStep::MouseDown { button: MouseButton::Left },
+            MacroStep::Wait { ms: 5000 },
+            MacroStep::MouseUp { button: MouseButton::Left },
+        ];
+        
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        let result = executor.run_steps("Hold", &steps, &fast_config(), Instant::now());
+        canceller.join().unwrap();
+        
+        assert!(matches!(result, Err(MacroError::Cancelled)));
+        assert_eq!(recorder.events(), [MouseDown(MouseButton::Left), MouseUp(MouseButton::Left)]);
+    }
+    
+    #[test]
+    fn invalid_config_sends_nothing() {
+        let (mut executor, recorder) = executor();
+        let bad_emote = Config { emote_slot: 9, ..fast_config() };
+        let bad_gear = Config { gear_slot: "x".to_string(), ..fast_config() };
+        
+        let field = |result: Result<(), MacroError>| match result {
+            Err(MacroError::Config(e)) => e.field(),
+            _ => None,
+        };
+        assert_eq!(field(execute(&mut executor, MacroKind::ComOffset, &bad_emote)), Some(ConfigField::EmoteSlot));
+        assert_eq!(field(execute(&mut executor, MacroKind::WallClip, &bad_gear)), Some(ConfigField::GearSlot));
+        assert!(recorder.events().is_empty());
+    }
+    
+    #[test]
+    fn cancelled_before_start_sends_nothing() {
+        let (mut executor, recorder) = executor();
+        let cancel = CancelToken::default();
+        executor.set_cancel_token(cancel.clone());
+        cancel.cancel();
+        
+        assert!(matches!(execute(&mut executor, MacroKind::ComOffset, &fast_config()), Err(MacroError::Cancelled)));
+        assert!(recorder.events().is_empty());
+    }
+    
+    #[test]
+    fn cancel_during_peak_delay_stops_before_gear() {
+        let (mut executor, recorder) = executor();
+        let cancel = CancelToken::default();
+        executor.set_cancel_token(cancel.clone());
+        let config = Config { peak_delay: 2.0, ..fast_config() };
+        
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            cancel.cancel();
+        });
+        let started = Instant::now();
+        let result = execute(&mut executor, MacroKind::ComOffset, &config);
+        canceller.join().unwrap();
+        
+        assert!(matches!(result, Err(MacroError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(recorder.events(), emote_prefix(0x31));
+    }
+    
+    #[test]
+    fn cancel_during_wall_clip_hold_releases_keys() {
+        let (mut executor, recorder) = executor();
+        let cancel = CancelToken::default();
+        executor.set_cancel_token(cancel.clone());
+        let config = Config { unshiftlock_after: true, ..fast_config() };
+        
+        // W is held from roughly 140 ms to 390 ms into the macro
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            cancel.cancel();
+        });
+        let result = execute(&mut executor, MacroKind::WallClip, &config);
+        canceller.join().unwrap();
+        
+        assert!(matches!(result, Err(MacroError::Cancelled)));
+        let expected = [
+            emote_prefix(0x31),
+            vec![KeyDown(VK_LSHIFT), KeyDown(VK_W), KeyUp(VK_W), KeyUp(VK_LSHIFT)],
+        ].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn failed_hold_releases_shiftlock() {
+        let recorder = RecordingBackend::new().with_fault(KeyDown(VK_W), Fault::Error);
+        let mut executor = MacroExecutor::with_backend(Box::new(recorder.clone()));
+        
+        let result = execute(&mut executor, MacroKind::WallClip, &fast_config());
+        assert!(matches!(result, Err(MacroError::Input(InputError::Send { .. }))));
+        
+        let expected = [emote_prefix(0x31), vec![KeyDown(VK_LSHIFT), KeyUp(VK_LSHIFT)]].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn failed_release_is_retried() {
+        let recorder = RecordingBackend::new().with_fault(KeyUp(VK_W), Fault::ErrorOnce);
+        let mut executor = MacroExecutor::with_backend(Box::new(recorder.clone()));
+        
+        let result = execute(&mut executor, MacroKind::WallClip, &fast_config());
+        assert!(matches!(result, Err(MacroError::Input(InputError::Send { .. }))));
+        
+        let expected = [emote_prefix(0x31), vec![KeyDown(VK_LSHIFT), KeyDown(VK_W), KeyUp(VK_W), KeyUp(VK_LSHIFT)]].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn panic_while_holding_releases_shiftlock() {
+        let recorder = RecordingBackend::new().with_fault(KeyDown(VK_W), Fault::Panic);
+        let mut executor = MacroExecutor::with_backend(Box::new(recorder.clone()));
+        
+        let result = panic::catch_unwind(AssertUnwindSafe(|| execute(&mut executor, MacroKind::WallClip, &fast_config())));
+        
+        assert!(result.is_err());
+        assert_eq!(recorder.events().last(), Some(&KeyUp(VK_LSHIFT)));
+    }
+    
+    #[test]
+    fn dropping_executor_releases_held_keys() {
+        let (mut executor, recorder) = executor();
+        executor.hold_key(VK_W).unwrap();
+        
+        drop(executor);
+        
+        assert_eq!(recorder.events(), [KeyDown(VK_W), KeyUp(VK_W)]);
+    }
+    
+    #[test]
+    fn release_all_keys_covers_recovery_keys() {
+        let (mut executor, recorder) = executor();
+        executor.hold_key(0x4B).unwrap();
+        let keymap = GameKeymap { shiftlock: VK_LCONTROL, ..GameKeymap::default() };
+        
+        executor.release_all_keys(&keymap).unwrap();
+        
+        let expected = [
+            KeyDown(0x4B), KeyUp(0x4B),
+            KeyUp(VK_LCONTROL), KeyUp(VK_W), KeyUp(0x53), KeyUp(0x41), KeyUp(0x44), KeyUp(0x20),
+        ];
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn release_all_keys_continues_past_a_failed_release() {
+        let recorder = RecordingBackend::new().with_fault(KeyUp(VK_W), Fault::Error);
+        let mut executor = MacroExecutor::with_backend(Box::new(recorder.clone()));
+        
+        let result = executor.release_all_keys(&GameKeymap::default());
+        
+        assert!(matches!(result, Err(MacroError::Input(_))));
+        assert_eq!(recorder.events(), [KeyUp(VK_LSHIFT), KeyUp(0x53), KeyUp(0x41), KeyUp(0x44), KeyUp(0x20)]);
+    }
+    
+    #[test]
+    fn macros_press_keys_from_the_keymap() {
+        let (mut executor, recorder) = executor();
+        // AZERTY player with the emote menu moved to comma
+        let config = Config {
+            keymap: GameKeymap { emote_menu: 0xBC, move_forward: 0x5A, ..GameKeymap::default() },
+            ..fast_config()
+        };
+        
+        execute(&mut executor, MacroKind::WallClip, &config).unwrap();
+        
+        let expected = [
+            tap(0xBC).to_vec(),
+            tap(0x31).to_vec(),
+            vec![KeyDown(VK_LSHIFT), KeyDown(0x5A), KeyUp(0x5A), KeyUp(VK_LSHIFT)],
+        ].concat();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn first_input_time_is_tracked_per_run() {
+        let (mut executor, _recorder) = executor();
+        let config = fast_config();
+        let steps = [MacroStep::Wait { ms: 30 }, MacroStep::KeyPress { key: KeyRef::Vk(0x41) }];
+        
+        let started = Instant::now();
+        executor.run_steps("test", &steps, &config, started).unwrap();
+        let first = executor.first_input_at().unwrap();
+        assert!(first - started >= Duration::from_millis(30));
+        
+        executor.run_steps("test", &[MacroStep::Wait { ms: 1 }], &config, Instant::now()).unwrap();
+        assert_eq!(executor.first_input_at(), None);
+    }
+    
+    #[test]
+    fn slow_input_does_not_push_back_later_steps() {
+        // Each event takes 40 ms, which would add 160 ms before the gear if steps slept back to back
+        let recorder = RecordingBackend::new().with_latency(Duration::from_millis(40));
+        let mut executor = MacroExecutor::with_backend(Box::new(recorder.clone()));
+        let config = Config { gear_slot: "7".to_string(), peak_delay: 0.2, unequip_after: false, ..fast_config() };
+        
+        execute(&mut executor, MacroKind::ComOffset, &config).unwrap();
+        
+        // Due at the 200 ms peak delay, recorded once its own 40 ms send completes
+        let gear_down = recorder.time_of(KeyDown(0x37)).map(|at| at.as_millis());
+        assert!(gear_down.is_some_and(|at| (240..320).contains(&at)), "{:?}", gear_down);
+        
+        let timings = executor.timings();
+        let steps: Vec<(&str, u128)> = timings.iter().map(|t| (t.step.as_str(), t.due.as_millis())).collect();
+        assert_eq!(steps, [("press emote menu", 0), ("press emote slot 1", 60), ("press gear slot 7", 200)]);
+        // The emote press waits on the slow period press, the gear press catches up during the peak delay
+        assert!(timings[1].late >= Duration::from_millis(20));
+        assert!(timings[2].late < timings[1].late);
+    }
+}
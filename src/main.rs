@@ -3,9 +3,19 @@
 use eframe::egui;
 
 mod config;
+mod dispatch;
+mod error;
 mod hotkeys;
+mod input;
+mod keys;
 mod macros;
+mod profiles;
+mod recorder;
+mod steps;
+mod timer;
 mod ui;
+mod user_macros;
+mod worker;
 
 use ui::MacroApp;
 
@@ -45,8 +55,8 @@ fn load_icon() -> egui::IconData {
         
         egui::IconData {
             rgba,
-            width: width as u32,
-            height: height as u32,
+            width,
+            height,
         }
     } else {
         // Fallback icon if loading fails
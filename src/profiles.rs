@@ -0,0 +1,321 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::config::{self, Config};
+use crate::error::{ConfigError, ProfileError};
+use crate::hotkeys::Action;
+
+const PROFILES_FILE_NAME: &str = "profiles.json";
+pub const DEFAULT_PROFILE_NAME: &str = "Default";
+
+/// Named set of configs, one of which is active at a time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStore {
+    pub active: String,
+    pub profiles: BTreeMap<String, Config>,
+    /// Key combo that switches to each profile, by profile name. Shared by every profile
+    /// so the way back stays bound after a switch.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub switch_hotkeys: BTreeMap<String, String>,
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self::with_config(Config::default())
+    }
+}
+
+impl ProfileStore {
+    fn with_config(config: Config) -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE_NAME.to_string(), config);
+        Self {
+            active: DEFAULT_PROFILE_NAME.to_string(),
+            profiles,
+            switch_hotkeys: BTreeMap::new(),
+        }
+    }
+    
+    pub fn profiles_path() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join(PROFILES_FILE_NAME))
+    }
+    
+    /// Loads the saved profiles, importing a single-config `config.json` from older versions if present.
+    /// The second value says why a saved file could not be used, defaults are loaded instead.
+    pub fn load() -> (Self, Option<ConfigError>) {
+        match Self::profiles_path() {
+            Some(path) => Self::load_from(&path, Config::config_path().as_deref()),
+            None => (Self::default(), Some(ConfigError::NoConfigDir)),
+        }
+    }
+    
+    /// Loads the profiles at `path`, or imports `legacy_path` when there are none yet
+    fn load_from(path: &Path, legacy_path: Option<&Path>) -> (Self, Option<ConfigError>) {
+        match config::read_json::<Self>(path) {
+            Ok(Some(mut store)) => {
+                store.repair();
+                (store, None)
+            }
+            Ok(None) => match legacy_path {
+                Some(legacy_path) => Self::import_legacy(legacy_path),
+                None => (Self::default(), None),
+            },
+            Err(e) => (Self::default(), Some(e)),
+        }
+    }
+    
+    fn import_legacy(path: &Path) -> (Self, Option<ConfigError>) {
+        let (config, warning) = Config::load_from(path);
+        (Self::with_config(config), warning)
+    }
+    
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let path = Self::profiles_path().ok_or(ConfigError::NoConfigDir)?;
+        config::write_json(&path, self)
+    }
+    
+    /// Makes sure there is at least one profile and that `active` points at one of them,
+    /// and brings profiles saved by older versions up to date
+    fn repair(&mut self) {
+        for config in self.profiles.values_mut() {
+            config.migrate_legacy_fields();
+        }
+        self.migrate_switch_hotkeys();
+        if self.profiles.is_empty() {
+            self.profiles.insert(DEFAULT_PROFILE_NAME.to_string(), Config::default());
+        }
+        if !self.profiles.contains_key(&self.active) {
+            self.active = self.profiles.keys().next().cloned().unwrap_or_default();
+        }
+    }
+    
+    /// Moves the switch bindings that older versions kept in each profile into `switch_hotkeys`,
+    /// taking the active profile's where they disagree
+    fn migrate_switch_hotkeys(&mut self) {
+        let (active, others): (Vec<_>, Vec<_>) = self.profiles.iter_mut()
+            .partition(|(name, _)| **name == self.active);
+        for (_, config) in active.into_iter().chain(others) {
+            config.hotkeys.retain(|id, binding| {
+                let Some(Action::SwitchProfile(name)) = Action::from_id(id) else {
+                    return true;
+                };
+                if !binding.trim().is_empty() {
+                    self.switch_hotkeys.entry(name).or_insert_with(|| binding.clone());
+                }
+                false
+            });
+        }
+    }
+    
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.profiles.keys()
+    }
+    
+    pub fn active_config(&self) -> &Config {
+        &self.profiles[&self.active]
+    }
+    
+    pub fn set_active_config(&mut self, config: Config) {
+        self.profiles.insert(self.active.clone(), config);
+    }
+    
+    /// The key combo that switches to `name`, if one is set
+    pub fn switch_hotkey(&self, name: &str) -> Option<&str> {
+        self.switch_hotkeys.get(name).map(|key| key.trim()).filter(|key| !key.is_empty())
+    }
+    
+    pub fn switch(&mut self, name: &str) -> Result<&Config, ProfileError> {
+        if !self.profiles.contains_key(name) {
+            return Err(ProfileError::NotFound(name.to_string()));
+        }
+        self.active = name.to_string();
+        Ok(self.active_config())
+    }
+    
+    /// Creates a profile with default settings and makes it active
+    pub fn create(&mut self, name: &str) -> Result<(), ProfileError> {
+        let name = self.validate_new_name(name)?;
+        self.profiles.insert(name.clone(), Config::default());
+        self.active = name;
+        Ok(())
+    }
+    
+    /// Copies the active profile under a new name and makes the copy active
+    pub fn duplicate(&mut self, name: &str) -> Result<(), ProfileError> {
+        let name = self.validate_new_name(name)?;
+        let config = self.active_config().clone();
+        self.profiles.insert(name.clone(), config);
+        self.active = name;
+        Ok(())
+    }
+    
+    /// Renames the active profile, moving the hotkey that switches to it over to the new name
+    pub fn rename_active(&mut self, name: &str) -> Result<(), ProfileError> {
+        let name = self.validate_new_name(name)?;
+        if let Some(config) = self.profiles.remove(&self.active) {
+            self.profiles.insert(name.clone(), config);
+        }
+        if let Some(binding) = self.switch_hotkeys.remove(&self.active) {
+            self.switch_hotkeys.insert(name.clone(), binding);
+        }
+        self.active = name;
+        Ok(())
+    }
+    
+    /// Deletes the active profile and its switch hotkey, and activates the first remaining one
+    pub fn delete_active(&mut self) -> Result<(), ProfileError> {
+        if self.profiles.len() <= 1 {
+            return Err(ProfileError::LastProfile);
+        }
+        self.profiles.remove(&self.active);
+        self.switch_hotkeys.remove(&self.active);
+        self.repair();
+        Ok(())
+    }
+    
+    fn validate_new_name(&self, name: &str) -> Result<String, ProfileError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ProfileError::EmptyName);
+        }
+        if self.profiles.contains_key(name) {
+            return Err(ProfileError::AlreadyExists(name.to_string()));
+        }
+        Ok(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    
+    fn store_with(names: &[&str]) -> ProfileStore {
+        let mut store = ProfileStore::default();
+        for name in names {
+            store.create(name).unwrap();
+        }
+        store
+    }
+    
+    #[test]
+    fn create_and_duplicate_activate_the_new_profile() {
+        let mut store = ProfileStore::default();
+        store.create(" Ranked ").unwrap();
+        assert_eq!(store.active, "Ranked");
+        assert_eq!(store.active_config(), &Config::default());
+        
+        let config = Config { peak_delay: 1.5, ..Config::default() };
+        store.set_active_config(config.clone());
+        store.duplicate("Ranked copy").unwrap();
+        assert_eq!(store.active, "Ranked copy");
+        assert_eq!(store.active_config(), &config);
+        assert_eq!(store.names().collect::<Vec<_>>(), ["Default", "Ranked", "Ranked copy"]);
+        
+        assert_eq!(store.create("Ranked").unwrap_err(), ProfileError::AlreadyExists("Ranked".to_string()));
+        assert_eq!(store.duplicate("  ").unwrap_err(), ProfileError::EmptyName);
+        assert_eq!(store.switch("Casual").unwrap_err(), ProfileError::NotFound("Casual".to_string()));
+    }
+    
+    #[test]
+    fn switch_hotkeys_outlive_switching() {
+        let mut store = store_with(&["Ranked"]);
+        store.switch_hotkeys.insert("Default".to_string(), "f1".to_string());
+        store.switch_hotkeys.insert("Ranked".to_string(), "f2".to_string());
+        
+        store.switch("Default").unwrap();
+        assert_eq!(store.switch_hotkey("Ranked"), Some("f2"));
+        store.switch("Ranked").unwrap();
+        assert_eq!(store.switch_hotkey("Default"), Some("f1"));
+    }
+    
+    #[test]
+    fn rename_moves_the_switch_hotkey() {
+        let mut store = store_with(&["Ranked"]);
+        store.switch_hotkeys.insert("Ranked".to_string(), "f2".to_string());
+        
+        store.rename_active("Scrims").unwrap();
+        assert_eq!(store.active, "Scrims");
+        assert_eq!(store.names().collect::<Vec<_>>(), ["Default", "Scrims"]);
+        assert_eq!(store.switch_hotkey("Scrims"), Some("f2"));
+        assert_eq!(store.switch_hotkey("Ranked"), None);
+    }
+    
+    #[test]
+    fn delete_drops_the_switch_hotkey_and_keeps_the_last_profile() {
+        let mut store = store_with(&["Ranked"]);
+        store.switch_hotkeys.insert("Ranked".to_string(), "f2".to_string());
+        
+        store.delete_active().unwrap();
+        assert_eq!(store.active, "Default");
+        assert_eq!(store.switch_hotkey("Ranked"), None);
+        assert_eq!(store.delete_active().unwrap_err(), ProfileError::LastProfile);
+    }
+    
+    #[test]
+    fn per_profile_switch_hotkeys_are_shared_on_load() {
+        let mut store = store_with(&["Ranked"]);
+        let to_ranked = Action::SwitchProfile("Ranked".to_string()).id();
+        let to_default = Action::SwitchProfile("Default".to_string()).id();
+        store.profiles.get_mut("Default").unwrap().hotkeys.insert(to_ranked.clone(), "f2".to_string());
+        let ranked = store.profiles.get_mut("Ranked").unwrap();
+        ranked.hotkeys.insert(to_ranked.clone(), "f3".to_string());
+        ranked.hotkeys.insert(to_default, "f1".to_string());
+        
+        store.repair();
+        // The active profile's binding wins
+        assert_eq!(store.switch_hotkey("Ranked"), Some("f3"));
+        assert_eq!(store.switch_hotkey("Default"), Some("f1"));
+        assert!(store.profiles.values().all(|config| config.hotkeys.keys().all(|id| !id.starts_with("profile:"))));
+    }
+    
+    #[test]
+    fn repair_fixes_a_missing_active_profile() {
+        let mut store: ProfileStore = serde_json::from_str(r#"{"active": "Gone", "profiles": {}}"#).unwrap();
+        store.repair();
+        assert_eq!(store, ProfileStore::default());
+        
+        let mut store = store_with(&["Ranked"]);
+        store.active = "Gone".to_string();
+        store.repair();
+        assert_eq!(store.active, "Default");
+    }
+    
+    #[test]
+    fn saved_profiles_load_back() {
+        let dir = std::env::temp_dir().join(format!("moonwalk-profiles-test-{}", std::process::id()));
+        let path = dir.join(PROFILES_FILE_NAME);
+        let legacy_path = dir.join("config.json");
+        let mut store = store_with(&["Ranked"]);
+        store.set_active_config(Config { emote_slot: 4, ..Config::default() });
+        
+        config::write_json(&path, &store).unwrap();
+        let (loaded, warning) = ProfileStore::load_from(&path, Some(&legacy_path));
+        fs::remove_file(&path).unwrap();
+        
+        config::write_json(&legacy_path, &Config { emote_slot: 6, ..Config::default() }).unwrap();
+        let (imported, import_warning) = ProfileStore::load_from(&path, Some(&legacy_path));
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(loaded, store);
+        assert!(warning.is_none());
+        assert_eq!(imported.active, DEFAULT_PROFILE_NAME);
+        assert_eq!(imported.active_config().emote_slot, 6);
+        assert!(import_warning.is_none());
+    }
+    
+    #[test]
+    fn corrupt_profiles_load_defaults_and_say_why() {
+        let dir = std::env::temp_dir().join(format!("moonwalk-profiles-corrupt-test-{}", std::process::id()));
+        let path = dir.join(PROFILES_FILE_NAME);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "{\"active\": ").unwrap();
+        
+        let (store, error) = ProfileStore::load_from(&path, None);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(store, ProfileStore::default());
+        assert!(matches!(error, Some(ConfigError::Corrupt { backup, .. }) if backup == dir.join("profiles.json.bak")));
+    }
+}
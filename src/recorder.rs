@@ -0,0 +1,370 @@
+// Records the user's real key presses with device_query so they can be replayed as a macro
+use crossbeam_channel::bounded;
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::error::RecorderError;
+use crate::input::InputBackendKind;
+use crate::steps::{KeyRef, MacroStep};
+use crate::user_macros::MAX_WAIT_MS;
+
+/// How often the keyboard state is sampled while recording
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    /// Time since the first recorded event
+    pub at: Duration,
+    pub vk: u16,
+    pub pressed: bool,
+}
+
+/// A captured timeline where every key that goes down also comes back up
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    events: Vec<KeyEvent>,
+}
+
+impl Recording {
+    /// Cleans up raw events: releases without a press (keys already held when recording started)
+    /// are dropped, keys still held at the end are released there, and times start at zero.
+    pub fn from_events(raw: &[KeyEvent]) -> Self {
+        let mut held = Vec::new();
+        let mut events = Vec::new();
+        
+        for event in raw {
+            if event.pressed == held.contains(&event.vk) {
+                continue;
+            }
+            if event.pressed {
+                held.push(event.vk);
+            } else {
+                held.retain(|&vk| vk != event.vk);
+            }
+            events.push(*event);
+        }
+        
+        let end = events.last().map_or(Duration::ZERO, |e| e.at);
+        events.extend(held.into_iter().map(|vk| KeyEvent { at: end, vk, pressed: false }));
+        
+        let start = events.first().map_or(Duration::ZERO, |e| e.at);
+        for event in &mut events {
+            event.at -= start;
+        }
+        Self { events }
+    }
+    
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+    
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |e| e.at)
+    }
+    
+    /// Drops the keys `backend` can't replay, returning them so the user can be told
+    pub fn retain_sendable(&mut self, backend: InputBackendKind) -> Vec<u16> {
+        let mut dropped = Vec::new();
+        self.events.retain(|e| {
+            let sendable = backend.can_send(e.vk);
+            if !sendable && !dropped.contains(&e.vk) {
+                dropped.push(e.vk);
+            }
+            sendable
+        });
+        dropped
+    }
+    
+    /// Keeps only what happened between `start` and `end`
+    pub fn trimmed(&self, start: Duration, end: Duration) -> Self {
+        let kept: Vec<KeyEvent> = self.events.iter()
+            .filter(|e| e.at >= start && e.at <= end)
+            .copied()
+            .collect();
+        Self::from_events(&kept)
+    }
+    
+    /// Steps that replay the recording with the same relative timing, to the millisecond
+    pub fn to_steps(&self) -> Vec<MacroStep> {
+        let mut steps = Vec::new();
+        let mut last_ms = 0;
+        
+        for event in &self.events {
+            // Waits are taken from absolute times so rounding doesn't add up over long recordings
+            let at_ms = event.at.as_millis() as u64;
+            let mut gap = at_ms - last_ms;
+            while gap > 0 {
+                let ms = gap.min(MAX_WAIT_MS);
+                steps.push(MacroStep::Wait { ms });
+                gap -= ms;
+            }
+            last_ms = at_ms;
+            
+            let key = KeyRef::Vk(event.vk);
+            steps.push(if event.pressed { MacroStep::KeyDown { key } } else { MacroStep::KeyUp { key } });
+        }
+        steps
+    }
+}
+
+/// A recording in progress. The keyboard is polled on its own thread until `stop`.
+pub struct Recorder {
+    events: Arc<Mutex<Vec<KeyEvent>>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    /// Starts capturing, ignoring the keys in `ignored` (such as the record hotkey itself)
+    pub fn start(ignored: Vec<u16>) -> Result<Self, RecorderError> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = bounded(1);
+        
+        let thread_events = events.clone();
+        let thread_stop = stop.clone();
+        let handle = thread::Builder::new()
+            .name("macro-recorder".to_string())
+            .spawn(move || {
+                // DeviceState isn't Send on every platform, so it has to be opened on this thread
+                let Some(device) = DeviceState::checked_new() else {
+                    let _ = ready_tx.send(Err(RecorderError::NoKeyboard));
+                    return;
+                };
+                let _ = ready_tx.send(Ok(()));
+                poll_keys(&device, &ignored, &thread_events, &thread_stop);
+            })
+            .map_err(RecorderError::Spawn)?;
+        
+        ready_rx.recv().map_err(|_| RecorderError::ThreadExited)??;
+        Ok(Self { events, stop, handle: Some(handle) })
+    }
+    
+    /// Raw events captured so far, for showing progress while recording
+    pub fn events(&self) -> Vec<KeyEvent> {
+        self.events.lock().map(|events| events.clone()).unwrap_or_default()
+    }
+    
+    pub fn stop(mut self) -> Recording {
+        self.join();
+        Recording::from_events(&self.events())
+    }
+    
+    fn join(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn poll_keys(device: &DeviceState, ignored: &[u16], events: &Mutex<Vec<KeyEvent>>, stop: &AtomicBool) {
+    let started = Instant::now();
+    // Keys already down count as held, so only their release is seen (and later discarded)
+    let mut held = pressed_keys(device, ignored);
+    
+    while !stop.load(Ordering::SeqCst) {
+        thread::sleep(POLL_INTERVAL);
+        let now = pressed_keys(device, ignored);
+        let changes = diff_keys(&held, &now, started.elapsed());
+        if !changes.is_empty() {
+            if let Ok(mut events) = events.lock() {
+                events.extend(changes);
+            }
+        }
+        held = now;
+    }
+}
+
+fn pressed_keys(device: &DeviceState, ignored: &[u16]) -> Vec<u16> {
+    device.get_keys().into_iter()
+        .filter_map(keycode_to_vk)
+        .filter(|vk| !ignored.contains(vk))
+        .collect()
+}
+
+/// Whether a Windows/Super key is held. egui reports it on macOS only, so elsewhere the keyboard is asked.
+pub fn logo_key_down() -> bool {
+    if cfg!(target_os = "macos") {
+        return false;
+    }
+    // VK_LWIN and VK_RWIN
+    DeviceState::checked_new()
+        .is_some_and(|device| pressed_keys(&device, &[]).iter().any(|vk| matches!(vk, 0x5B | 0x5C)))
+}
+
+/// Events that turn the `held` key set into `now`, releases first
+fn diff_keys(held: &[u16], now: &[u16], at: Duration) -> Vec<KeyEvent> {
+    let released = held.iter()
+        .filter(|vk| !now.contains(vk))
+        .map(|&vk| KeyEvent { at, vk, pressed: false });
+    let pressed = now.iter()
+        .filter(|vk| !held.contains(vk))
+        .map(|&vk| KeyEvent { at, vk, pressed: true });
+    released.chain(pressed).collect()
+}
+
+/// Windows virtual-key code for a device_query key
+pub fn keycode_to_vk(key: Keycode) -> Option<u16> {
+    use Keycode::*;
+    
+    Some(match key {
+        Key0 => 0x30, Key1 => 0x31, Key2 => 0x32, Key3 => 0x33, Key4 => 0x34,
+        Key5 => 0x35, Key6 => 0x36, Key7 => 0x37, Key8 => 0x38, Key9 => 0x39,
+        A => 0x41, B => 0x42, C => 0x43, D => 0x44, E => 0x45, F => 0x46, G => 0x47,
+        H => 0x48, I => 0x49, J => 0x4A, K => 0x4B, L => 0x4C, M => 0x4D, N => 0x4E,
+        O => 0x4F, P => 0x50, Q => 0x51, R => 0x52, S => 0x53, T => 0x54, U => 0x55,
+        V => 0x56, W => 0x57, X => 0x58, Y => 0x59, Z => 0x5A,
+        F1 => 0x70, F2 => 0x71, F3 => 0x72, F4 => 0x73, F5 => 0x74, F6 => 0x75,
+        F7 => 0x76, F8 => 0x77, F9 => 0x78, F10 => 0x79, F11 => 0x7A, F12 => 0x7B,
+        F13 => 0x7C, F14 => 0x7D, F15 => 0x7E, F16 => 0x7F, F17 => 0x80, F18 => 0x81,
+        F19 => 0x82, F20 => 0x83,
+        Escape => 0x1B,
+        Space => 0x20,
+        LControl => 0xA2,
+        RControl => 0xA3,
+        LShift => 0xA0,
+        RShift => 0xA1,
+        LAlt | LOption => 0xA4,
+        RAlt | ROption => 0xA5,
+        Command | LMeta => 0x5B,
+        RMeta => 0x5C,
+        Enter | NumpadEnter => 0x0D,
+        Up => 0x26,
+        Down => 0x28,
+        Left => 0x25,
+        Right => 0x27,
+        Backspace => 0x08,
+        CapsLock => 0x14,
+        Tab => 0x09,
+        Home => 0x24,
+        End => 0x23,
+        PageUp => 0x21,
+        PageDown => 0x22,
+        Insert => 0x2D,
+        Delete => 0x2E,
+        Numpad0 => 0x60, Numpad1 => 0x61, Numpad2 => 0x62, Numpad3 => 0x63, Numpad4 => 0x64,
+        Numpad5 => 0x65, Numpad6 => 0x66, Numpad7 => 0x67, Numpad8 => 0x68, Numpad9 => 0x69,
+        NumpadMultiply => 0x6A,
+        NumpadAdd => 0x6B,
+        NumpadSubtract => 0x6D,
+        NumpadDecimal => 0x6E,
+        NumpadDivide => 0x6F,
+        Grave => 0xC0,
+        Minus => 0xBD,
+        Equal => 0xBB,
+        LeftBracket => 0xDB,
+        RightBracket => 0xDD,
+        BackSlash => 0xDC,
+        Semicolon => 0xBA,
+        Apostrophe => 0xDE,
+        Comma => 0xBC,
+        Dot => 0xBE,
+        Slash => 0xBF,
+        NumpadEquals => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{VK_LSHIFT, VK_W};
+    
+    fn event(ms: u64, vk: u16, pressed: bool) -> KeyEvent {
+        KeyEvent { at: Duration::from_millis(ms), vk, pressed }
+    }
+    
+    #[test]
+    fn diff_reports_releases_and_presses() {
+        let at = Duration::from_millis(7);
+        assert_eq!(diff_keys(&[VK_W, VK_LSHIFT], &[VK_LSHIFT, 0x41], at), [
+            KeyEvent { at, vk: VK_W, pressed: false },
+            KeyEvent { at, vk: 0x41, pressed: true },
+        ]);
+    }
+    
+    #[test]
+    fn recording_balances_presses_and_starts_at_zero() {
+        let recording = Recording::from_events(&[
+            event(100, VK_LSHIFT, false), // Held before recording started
+            event(120, VK_W, true),
+            event(130, VK_W, true),
+            event(200, VK_LSHIFT, true),
+            event(370, VK_W, false),
+        ]);
+        
+        assert_eq!(recording.events(), [
+            event(0, VK_W, true),
+            event(80, VK_LSHIFT, true),
+            event(250, VK_W, false),
+            event(250, VK_LSHIFT, false),
+        ]);
+        assert_eq!(recording.duration(), Duration::from_millis(250));
+    }
+    
+    #[test]
+    fn trimming_keeps_the_window_and_rebalances() {
+        let recording = Recording::from_events(&[
+            event(0, 0x41, true),
+            event(50, 0x41, false),
+            event(100, VK_W, true),
+            event(300, VK_W, false),
+            event(400, 0x44, true),
+            event(500, 0x44, false),
+        ]);
+        
+        let trimmed = recording.trimmed(Duration::from_millis(40), Duration::from_millis(450));
+        assert_eq!(trimmed.events(), [
+            event(0, VK_W, true),
+            event(200, VK_W, false),
+            event(300, 0x44, true),
+            event(300, 0x44, false),
+        ]);
+    }
+    
+    #[test]
+    fn keys_the_backend_cannot_send_are_dropped() {
+        // F13 has no enigo mapping, but SendInput passes any virtual-key code through
+        let raw = [event(0, VK_W, true), event(10, 0x7C, true), event(20, 0x7C, false), event(30, VK_W, false)];
+        let mut recording = Recording::from_events(&raw);
+        assert!(recording.clone().retain_sendable(InputBackendKind::SendInput).is_empty());
+        
+        assert_eq!(recording.retain_sendable(InputBackendKind::Enigo), [0x7C]);
+        assert_eq!(recording.events(), [event(0, VK_W, true), event(30, VK_W, false)]);
+    }
+    
+    #[test]
+    fn steps_replay_relative_timing() {
+        let recording = Recording::from_events(&[
+            event(1000, VK_W, true),
+            event(1250, VK_LSHIFT, true),
+            event(61_250, VK_LSHIFT, false),
+            event(61_250, VK_W, false),
+        ]);
+        
+        assert_eq!(recording.to_steps(), [
+            MacroStep::KeyDown { key: KeyRef::Vk(VK_W) },
+            MacroStep::Wait { ms: 250 },
+            MacroStep::KeyDown { key: KeyRef::Vk(VK_LSHIFT) },
+            MacroStep::Wait { ms: 60_000 },
+            MacroStep::KeyUp { key: KeyRef::Vk(VK_LSHIFT) },
+            MacroStep::KeyUp { key: KeyRef::Vk(VK_W) },
+        ]);
+    }
+    
+    #[test]
+    fn letter_and_digit_keycodes_match_virtual_keys() {
+        assert_eq!(keycode_to_vk(Keycode::Key0), Some(0x30));
+        assert_eq!(keycode_to_vk(Keycode::Z), Some(0x5A));
+        assert_eq!(keycode_to_vk(Keycode::Dot), Some(crate::input::VK_PERIOD));
+        assert_eq!(keycode_to_vk(Keycode::LShift), Some(VK_LSHIFT));
+    }
+}
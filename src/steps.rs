@@ -0,0 +1,292 @@
+// Macros as data: a list of steps the executor interprets against the current config
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use crate::config::Config;
+use crate::error::{ConfigError, ConfigField, UnknownKey};
+use crate::input::{self, MouseButton};
+use crate::keys;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Press and release a key
+    KeyPress { key: KeyRef },
+    KeyDown { key: KeyRef },
+    KeyUp { key: KeyRef },
+    /// Move the mouse by `dx`, `dy` from where it is, e.g. a camera flick
+    MouseMove { dx: i32, dy: i32 },
+    MouseDown { button: MouseButton },
+    MouseUp { button: MouseButton },
+    /// Turn the wheel by `notches`, positive up (zooms in), negative down
+    Scroll { notches: i32 },
+    Wait { ms: u64 },
+    /// Wait until the config's `peak_delay` after the first step, however long the steps before took
+    WaitPeakDelay,
+    /// Wait for one of the config's step timings
+    WaitDelay { delay: ConfigDelay },
+    /// Hold the config's `wall_clip_movement` keys, each from its start offset for its hold time
+    WallClipMovement,
+    /// Run `steps` only when `flag` is set in the config
+    Conditional { flag: ConfigFlag, steps: Vec<MacroStep> },
+}
+
+/// A key that is either fixed or looked up from the config when the macro runs.
+/// Written as a key name (`"period"`, `"w"`), a raw virtual-key code (`"0xbe"`),
+/// one of `"emote_slot"`, `"gear_slot"`, or a game action from the keymap such as `"move_forward"`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum KeyRef {
+    Vk(u16),
+    EmoteSlot,
+    GearSlot,
+    /// Game actions, through the config's `keymap`
+    Shiftlock,
+    EmoteMenu,
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigFlag {
+    UnequipAfter,
+    UnshiftlockAfter,
+}
+
+/// A delay from the config's `timing` section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigDelay {
+    MenuGap,
+    UndoDelay,
+    ShiftlockGap,
+}
+
+impl ConfigDelay {
+    pub fn duration(self, config: &Config) -> Duration {
+        let timing = &config.timing;
+        Duration::from_millis(match self {
+            Self::MenuGap => timing.menu_gap_ms,
+            Self::UndoDelay => timing.undo_delay_ms,
+            Self::ShiftlockGap => timing.shiftlock_gap_ms,
+        })
+    }
+}
+
+impl ConfigFlag {
+    pub fn is_set(self, config: &Config) -> bool {
+        match self {
+            Self::UnequipAfter => config.unequip_after,
+            Self::UnshiftlockAfter => config.unshiftlock_after,
+        }
+    }
+}
+
+impl KeyRef {
+    /// Virtual-key code this key stands for under `config`
+    pub fn resolve(self, config: &Config) -> Result<u16, ConfigError> {
+        match self {
+            Self::Vk(vk_code) => Ok(vk_code),
+            Self::EmoteSlot => match config.emote_slot {
+                1..=8 => Ok(0x30 + config.emote_slot as u16),
+                _ => Err(ConfigError::invalid(ConfigField::EmoteSlot, "Invalid emote slot")),
+            },
+            Self::GearSlot => config.gear_slot.chars().next()
+                .and_then(input::vk_digit)
+                .ok_or_else(|| ConfigError::invalid(ConfigField::GearSlot, "Invalid gear slot")),
+            Self::Shiftlock => Ok(config.keymap.shiftlock),
+            Self::EmoteMenu => Ok(config.keymap.emote_menu),
+            Self::MoveForward => Ok(config.keymap.move_forward),
+            Self::MoveBack => Ok(config.keymap.move_back),
+            Self::MoveLeft => Ok(config.keymap.move_left),
+            Self::MoveRight => Ok(config.keymap.move_right),
+            Self::Jump => Ok(config.keymap.jump),
+        }
+    }
+    
+    /// Human readable name for progress messages
+    pub fn describe(self, config: &Config) -> String {
+        match self {
+            Self::Vk(vk_code) => keys::vk_name(vk_code).map_or_else(|| format!("VK {:#04x}", vk_code), str::to_string),
+            Self::EmoteSlot => format!("emote slot {}", config.emote_slot),
+            Self::GearSlot => format!("gear slot {}", config.gear_slot),
+            Self::Shiftlock => "shiftlock".to_string(),
+            Self::EmoteMenu => "emote menu".to_string(),
+            Self::MoveForward => "forward".to_string(),
+            Self::MoveBack => "back".to_string(),
+            Self::MoveLeft => "left".to_string(),
+            Self::MoveRight => "right".to_string(),
+            Self::Jump => "jump".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyRef {
+    type Error = UnknownKey;
+    
+    fn try_from(name: String) -> Result<Self, UnknownKey> {
+        match name.as_str() {
+            "emote_slot" => Ok(Self::EmoteSlot),
+            "gear_slot" => Ok(Self::GearSlot),
+            "shiftlock" => Ok(Self::Shiftlock),
+            "emote_menu" => Ok(Self::EmoteMenu),
+            "move_forward" => Ok(Self::MoveForward),
+            "move_back" => Ok(Self::MoveBack),
+            "move_left" => Ok(Self::MoveLeft),
+            "move_right" => Ok(Self::MoveRight),
+            "jump" => Ok(Self::Jump),
+            _ => keys::parse_vk(&name).map(Self::Vk).ok_or(UnknownKey(name)),
+        }
+    }
+}
+
+impl From<KeyRef> for String {
+    fn from(key: KeyRef) -> String {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for KeyRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vk(vk_code) => write!(f, "{}", keys::vk_label(*vk_code)),
+            Self::EmoteSlot => write!(f, "emote_slot"),
+            Self::GearSlot => write!(f, "gear_slot"),
+            Self::Shiftlock => write!(f, "shiftlock"),
+            Self::EmoteMenu => write!(f, "emote_menu"),
+            Self::MoveForward => write!(f, "move_forward"),
+            Self::MoveBack => write!(f, "move_back"),
+            Self::MoveLeft => write!(f, "move_left"),
+            Self::MoveRight => write!(f, "move_right"),
+            Self::Jump => write!(f, "jump"),
+        }
+    }
+}
+
+/// Opens the emote menu, picks the moonwalk emote and waits for the peak
+fn emote_to_peak() -> Vec<MacroStep> {
+    vec![
+        MacroStep::KeyPress { key: KeyRef::EmoteMenu },
+        MacroStep::WaitDelay { delay: ConfigDelay::MenuGap },
+        MacroStep::KeyPress { key: KeyRef::EmoteSlot },
+        MacroStep::WaitDelay { delay: ConfigDelay::MenuGap },
+        MacroStep::WaitPeakDelay,
+    ]
+}
+
+/// Equips the gear at the moonwalk peak, optionally unequipping it again
+pub fn com_offset() -> Vec<MacroStep> {
+    let mut steps = emote_to_peak();
+    steps.extend([
+        MacroStep::KeyPress { key: KeyRef::GearSlot },
+        MacroStep::Conditional {
+            flag: ConfigFlag::UnequipAfter,
+            steps: vec![
+                MacroStep::WaitDelay { delay: ConfigDelay::UndoDelay },
+                MacroStep::KeyPress { key: KeyRef::GearSlot },
+            ],
+        },
+    ]);
+    steps
+}
+
+/// Moves in shiftlock at the moonwalk peak, optionally toggling shiftlock off after
+pub fn wall_clip() -> Vec<MacroStep> {
+    let mut steps = emote_to_peak();
+    steps.extend([
+        MacroStep::KeyDown { key: KeyRef::Shiftlock },
+        MacroStep::WaitDelay { delay: ConfigDelay::ShiftlockGap },
+        MacroStep::WallClipMovement,
+        MacroStep::WaitDelay { delay: ConfigDelay::ShiftlockGap },
+        MacroStep::KeyUp { key: KeyRef::Shiftlock },
+        MacroStep::Conditional {
+            flag: ConfigFlag::UnshiftlockAfter,
+            steps: vec![
+                MacroStep::WaitDelay { delay: ConfigDelay::UndoDelay },
+                MacroStep::KeyPress { key: KeyRef::Shiftlock },
+            ],
+        },
+    ]);
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameKeymap;
+    use crate::input::{VK_LCONTROL, VK_PERIOD, VK_W};
+    
+    #[test]
+    fn builtin_macros_round_trip_through_json() {
+        for steps in [com_offset(), wall_clip()] {
+            let json = serde_json::to_string(&steps).unwrap();
+            let parsed: Vec<MacroStep> = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, steps);
+        }
+    }
+    
+    #[test]
+    fn steps_use_readable_json() {
+        let json = r#"[
+            {"type": "key_press", "key": "period"},
+            {"type": "wait", "ms": 50},
+            {"type": "key_down", "key": "shiftlock"},
+            {"type": "wait_peak_delay"},
+            {"type": "wait_delay", "delay": "menu_gap"},
+            {"type": "wall_clip_movement"},
+            {"type": "mouse_move", "dx": -120, "dy": 0},
+            {"type": "mouse_down", "button": "right"},
+            {"type": "scroll", "notches": -3},
+            {"type": "conditional", "flag": "unequip_after", "steps": [{"type": "key_up", "key": "W"}]}
+        ]"#;
+        let steps: Vec<MacroStep> = serde_json::from_str(json).unwrap();
+        
+        assert_eq!(steps, [
+            MacroStep::KeyPress { key: KeyRef::Vk(VK_PERIOD) },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::KeyDown { key: KeyRef::Shiftlock },
+            MacroStep::WaitPeakDelay,
+            MacroStep::WaitDelay { delay: ConfigDelay::MenuGap },
+            MacroStep::WallClipMovement,
+            MacroStep::MouseMove { dx: -120, dy: 0 },
+            MacroStep::MouseDown { button: MouseButton::Right },
+            MacroStep::Scroll { notches: -3 },
+            MacroStep::Conditional {
+                flag: ConfigFlag::UnequipAfter,
+                steps: vec![MacroStep::KeyUp { key: KeyRef::Vk(VK_W) }],
+            },
+        ]);
+    }
+    
+    #[test]
+    fn raw_vk_codes_round_trip() {
+        let key = KeyRef::Vk(0xE2);
+        assert_eq!(KeyRef::try_from(String::from(key)), Ok(key));
+    }
+    
+    #[test]
+    fn unknown_key_is_rejected() {
+        let result = serde_json::from_str::<MacroStep>(r#"{"type": "key_press", "key": "hyper"}"#);
+        assert!(result.unwrap_err().to_string().contains("unknown key 'hyper'"));
+    }
+    
+    #[test]
+    fn config_keys_resolve_against_config() {
+        let config = Config {
+            emote_slot: 6,
+            gear_slot: "0".to_string(),
+            keymap: GameKeymap { shiftlock: VK_LCONTROL, move_forward: 0x5A, ..GameKeymap::default() },
+            ..Config::default()
+        };
+        
+        assert_eq!(KeyRef::EmoteSlot.resolve(&config).unwrap(), 0x36);
+        assert_eq!(KeyRef::GearSlot.resolve(&config).unwrap(), 0x30);
+        assert_eq!(KeyRef::Shiftlock.resolve(&config).unwrap(), VK_LCONTROL);
+        assert_eq!(KeyRef::MoveForward.resolve(&config).unwrap(), 0x5A);
+        assert_eq!(KeyRef::EmoteMenu.resolve(&config).unwrap(), VK_PERIOD);
+    }
+}
@@ -0,0 +1,138 @@
+// Sleeps that land close to their deadline: the OS sleep gets near it, a spin covers the rest
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the overshoot histogram buckets, the last bucket takes everything above
+pub const HISTOGRAM_BUCKETS: [Duration; 6] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrecisionTimer {
+    /// How much of each wait is spun instead of slept. Zero sleeps the whole way.
+    spin_threshold: Duration,
+}
+
+impl PrecisionTimer {
+    pub fn new(spin_threshold: Duration) -> Self {
+        Self { spin_threshold }
+    }
+    
+    pub fn spin_threshold(&self) -> Duration {
+        self.spin_threshold
+    }
+    
+    /// Returns at `deadline` or just after it, never before
+    pub fn sleep_until(&self, deadline: Instant) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining > self.spin_threshold {
+            thread::sleep(remaining - self.spin_threshold);
+        }
+        while Instant::now() < deadline {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// How far past the deadline a batch of timed waits woke up
+#[derive(Debug, Clone, PartialEq)]
+pub struct OvershootStats {
+    /// Sorted, shortest first
+    samples: Vec<Duration>,
+}
+
+impl OvershootStats {
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        Self { samples }
+    }
+    
+    /// Overshoot that `fraction` (0.0 to 1.0) of the samples stayed at or below
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = ((self.samples.len() - 1) as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        self.samples[index]
+    }
+    
+    pub fn max(&self) -> Duration {
+        self.samples.last().copied().unwrap_or_default()
+    }
+    
+    /// Sample count per `HISTOGRAM_BUCKETS` entry, plus one for everything above the last
+    pub fn histogram(&self) -> Vec<usize> {
+        let mut counts = vec![0; HISTOGRAM_BUCKETS.len() + 1];
+        for sample in &self.samples {
+            let bucket = HISTOGRAM_BUCKETS.iter().position(|bound| sample < bound).unwrap_or(HISTOGRAM_BUCKETS.len());
+            counts[bucket] += 1;
+        }
+        counts
+    }
+}
+
+/// Plain `thread::sleep` against the hybrid timer on this machine
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    pub wait: Duration,
+    pub sleep: OvershootStats,
+    pub hybrid: OvershootStats,
+}
+
+/// Times `samples` waits of `wait` each, first with plain sleeps, then with `timer`
+pub fn benchmark(timer: PrecisionTimer, wait: Duration, samples: usize) -> BenchmarkReport {
+    let measure = |sleep_until: &dyn Fn(Instant)| {
+        let overshoots = (0..samples)
+            .map(|_| {
+                let deadline = Instant::now() + wait;
+                sleep_until(deadline);
+                Instant::now().saturating_duration_since(deadline)
+            })
+            .collect();
+        OvershootStats::new(overshoots)
+    };
+    
+    BenchmarkReport {
+        wait,
+        sleep: measure(&|deadline| thread::sleep(deadline.saturating_duration_since(Instant::now()))),
+        hybrid: measure(&|deadline| timer.sleep_until(deadline)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn never_wakes_before_the_deadline() {
+        for threshold in [Duration::ZERO, Duration::from_millis(1), Duration::from_millis(50)] {
+            let timer = PrecisionTimer::new(threshold);
+            let deadline = Instant::now() + Duration::from_millis(5);
+            timer.sleep_until(deadline);
+            assert!(Instant::now() >= deadline);
+        }
+    }
+    
+    #[test]
+    fn stats_summarise_samples() {
+        let stats = OvershootStats::new([40, 5, 700, 1500, 20_000].map(Duration::from_micros).to_vec());
+        
+        assert_eq!(stats.percentile(0.0), Duration::from_micros(5));
+        assert_eq!(stats.percentile(0.5), Duration::from_micros(700));
+        assert_eq!(stats.max(), Duration::from_millis(20));
+        assert_eq!(stats.histogram(), [1, 1, 0, 1, 1, 0, 1]);
+    }
+    
+    #[test]
+    fn benchmark_measures_both_timers() {
+        let report = benchmark(PrecisionTimer::new(Duration::from_millis(2)), Duration::from_millis(1), 5);
+        assert_eq!(report.sleep.histogram().iter().sum::<usize>(), 5);
+        assert_eq!(report.hybrid.histogram().iter().sum::<usize>(), 5);
+    }
+}
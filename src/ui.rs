@@ -1,15 +1,95 @@
 use eframe::egui;
-use crate::config::{Config, ShiftlockKey};
-use crate::macros::MacroExecutor;
-use crate::hotkeys::{HotkeyManager, HotkeyEvent};
+use global_hotkey::GlobalHotKeyEvent;
+use std::collections::BTreeMap;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::config::{self, Config, MovementKey, StepTimings};
+use crate::dispatch::{DispatchEvent, HotkeyDispatcher};
+use crate::error::{self, ConfigError, ConfigField, HotkeyError, InputError, MacroError, ProfileError, RecorderError, UserMacroError};
+use crate::macros::{MacroKind, StepTiming};
+use crate::hotkeys::{self, Action, HotkeyManager};
+use crate::input::InputBackendKind;
+use crate::keys;
+use crate::profiles::ProfileStore;
+use crate::recorder::{self, Recorder, Recording};
+use crate::steps::{KeyRef, MacroStep};
+use crate::timer::{self, BenchmarkReport, OvershootStats, PrecisionTimer};
+use crate::user_macros::{self, LoadError, UserMacro};
+use crate::worker::{MacroWorker, WorkerEvent};
 
 pub struct MacroApp {
     config: Config,
+    profiles: ProfileStore,
+    saved_profiles: ProfileStore,
+    /// When the profiles first differed from `saved_profiles`, `None` while they match
+    unsaved_since: Option<Instant>,
+    /// Set when the profiles couldn't be loaded and saving would fail or overwrite a file we couldn't read
+    saving_disabled: bool,
+    profile_name_input: String,
     status_message: String,
     status_type: StatusType,
     hotkey_manager: Option<HotkeyManager>,
-    capturing_hotkey: Option<HotkeyCapture>,
-    active_hotkeys: String,
+    worker: MacroWorker,
+    dispatcher: HotkeyDispatcher,
+    /// Time from the last trigger to its first key reaching the input backend
+    last_latency: Option<Duration>,
+    /// Lateness of each key step of the last finished macro
+    last_timings: Vec<StepTiming>,
+    capturing_hotkey: Option<Action>,
+    /// Bindings registered by the last successful apply, `None` until hotkeys are applied
+    applied_hotkeys: Option<Vec<(Action, String)>>,
+    /// Binding the last failed apply complained about, highlighted until an apply succeeds
+    hotkey_error: Option<Action>,
+    /// Set when the selected input backend failed, highlighted until it changes or a macro succeeds
+    backend_failed: bool,
+    user_macros: Vec<UserMacro>,
+    macro_load_errors: Vec<LoadError>,
+    recorder: Option<Recorder>,
+    recording: Option<Recording>,
+    trim_start_ms: u64,
+    trim_end_ms: u64,
+    recording_name: String,
+    benchmark: Option<JoinHandle<BenchmarkReport>>,
+    benchmark_report: Option<BenchmarkReport>,
+}
+
+/// How long changed settings wait before being written, so dragging a value doesn't write every frame
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+const BENCHMARK_WAIT: Duration = Duration::from_millis(5);
+const BENCHMARK_SAMPLES: usize = 200;
+
+/// Microseconds below a millisecond, milliseconds above
+fn format_duration(duration: Duration) -> String {
+    match duration.as_micros() {
+        micros @ 0..=999 => format!("{} µs", micros),
+        micros => format!("{:.2} ms", micros as f64 / 1000.0),
+    }
+}
+
+const fn hold(key: KeyRef, start_ms: u64, hold_ms: u64) -> MovementKey {
+    MovementKey { key, start_ms, hold_ms }
+}
+
+/// Common Wall Clip movements; "W + strafe" taps right partway through a forward walk
+const MOVEMENT_PRESETS: [(&str, &[MovementKey]); 7] = [
+    ("W", &[hold(KeyRef::MoveForward, 0, 250)]),
+    ("W+A", &[hold(KeyRef::MoveForward, 0, 250), hold(KeyRef::MoveLeft, 0, 250)]),
+    ("W+D", &[hold(KeyRef::MoveForward, 0, 250), hold(KeyRef::MoveRight, 0, 250)]),
+    ("A", &[hold(KeyRef::MoveLeft, 0, 250)]),
+    ("D", &[hold(KeyRef::MoveRight, 0, 250)]),
+    ("S", &[hold(KeyRef::MoveBack, 0, 250)]),
+    ("W + strafe", &[hold(KeyRef::MoveForward, 0, 250), hold(KeyRef::MoveRight, 100, 60)]),
+];
+
+fn movement_label(key: KeyRef) -> &'static str {
+    config::MOVEMENT_KEYS.iter().find(|(k, _)| *k == key).map_or("?", |(_, label)| label)
+}
+
+fn summarize(stats: &OvershootStats) -> String {
+    format!(
+        "median {}, p99 {}, max {}",
+        format_duration(stats.percentile(0.5)), format_duration(stats.percentile(0.99)), format_duration(stats.max())
+    )
 }
 
 #[derive(Debug, Clone)]
@@ -20,23 +100,124 @@ enum StatusType {
     Error,
 }
 
-#[derive(Debug, Clone)]
-enum HotkeyCapture {
-    Com,
-    Clip,
-}
-
 impl MacroApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         let hotkey_manager = HotkeyManager::new().ok();
+        let worker = MacroWorker::spawn(cc.egui_ctx.clone());
+        let dispatcher = HotkeyDispatcher::spawn(cc.egui_ctx.clone(), worker.handle(), GlobalHotKeyEvent::receiver().clone());
+        let (profiles, load_warning) = ProfileStore::load();
         
-        Self {
-            config: Config::default(),
+        let mut app = Self {
+            config: profiles.active_config().clone(),
+            saved_profiles: profiles.clone(),
+            unsaved_since: None,
+            saving_disabled: false,
+            profiles,
+            profile_name_input: String::new(),
             status_message: "Ready".to_string(),
             status_type: StatusType::Info,
             hotkey_manager,
+            worker,
+            dispatcher,
+            last_latency: None,
+            last_timings: Vec::new(),
             capturing_hotkey: None,
-            active_hotkeys: "None".to_string(),
+            applied_hotkeys: None,
+            hotkey_error: None,
+            backend_failed: false,
+            user_macros: Vec::new(),
+            macro_load_errors: Vec::new(),
+            recorder: None,
+            recording: None,
+            trim_start_ms: 0,
+            trim_end_ms: 0,
+            recording_name: String::new(),
+            benchmark: None,
+            benchmark_report: None,
+        };
+        
+        app.reload_macros();
+        if let Some(error) = load_warning {
+            app.report_load_error(&error);
+        }
+        
+        app
+    }
+    
+    /// Explains why the saved profiles weren't used. Saving is turned off when it can't work,
+    /// or when the file is still in place and could be read again once the problem is fixed.
+    fn report_load_error(&mut self, error: &ConfigError) {
+        let message = error::report(error);
+        match error {
+            // The old file was moved aside, so saving the defaults is safe
+            ConfigError::Corrupt { .. } => self.set_status(&message, StatusType::Warning),
+            ConfigError::NoConfigDir => {
+                self.saving_disabled = true;
+                self.set_status(&format!("{}, settings will not be saved", message), StatusType::Warning);
+            }
+            _ => {
+                self.saving_disabled = true;
+                self.set_status(&format!("{}. Using defaults, changes will not be saved", message), StatusType::Error);
+            }
+        }
+    }
+    
+    /// Writes the profiles to disk once they have differed from what was last saved for `SAVE_DELAY`,
+    /// and not while a value is still being dragged
+    fn auto_save(&mut self, ctx: &egui::Context) {
+        self.profiles.set_active_config(self.config.clone());
+        if self.saving_disabled || self.profiles == self.saved_profiles {
+            self.unsaved_since = None;
+            return;
+        }
+        
+        let unsaved_since = *self.unsaved_since.get_or_insert_with(Instant::now);
+        if unsaved_since.elapsed() >= SAVE_DELAY && !ctx.input(|i| i.pointer.any_down()) {
+            if let Err(e) = self.save_profiles() {
+                self.set_status(&format!("Failed to save settings: {}", error::report(&e)), StatusType::Error);
+            }
+        }
+    }
+    
+    fn save_profiles(&mut self) -> Result<(), ConfigError> {
+        // Remember the attempt even on failure so a broken disk doesn't retry every frame
+        self.saved_profiles = self.profiles.clone();
+        self.unsaved_since = None;
+        self.profiles.save()
+    }
+    
+    fn switch_profile(&mut self, name: &str) {
+        self.profiles.set_active_config(self.config.clone());
+        match self.profiles.switch(name) {
+            Ok(config) => {
+                self.config = config.clone();
+                self.set_status(&format!("Switched to profile '{}'", name), StatusType::Success);
+                self.reapply_hotkeys_if_changed();
+            }
+            Err(e) => self.set_status(&e.to_string(), StatusType::Error),
+        }
+    }
+    
+    /// Runs a create/rename/duplicate/delete operation and loads whichever profile ends up active
+    fn edit_profiles(&mut self, success: &str, op: impl FnOnce(&mut ProfileStore, &str) -> Result<(), ProfileError>) {
+        self.profiles.set_active_config(self.config.clone());
+        let name = std::mem::take(&mut self.profile_name_input);
+        match op(&mut self.profiles, &name) {
+            Ok(()) => {
+                self.config = self.profiles.active_config().clone();
+                let message = format!("{} (active: '{}')", success, self.profiles.active);
+                self.set_status(&message, StatusType::Success);
+                self.reapply_hotkeys_if_changed();
+            }
+            Err(e) => {
+                // Leave the typed name in place so it can be corrected
+                self.profile_name_input = name;
+                let status_type = match e {
+                    ProfileError::EmptyName | ProfileError::AlreadyExists(_) | ProfileError::LastProfile => StatusType::Warning,
+                    ProfileError::NotFound(_) => StatusType::Error,
+                };
+                self.set_status(&e.to_string(), status_type);
+            }
         }
     }
     
@@ -45,45 +226,316 @@ impl MacroApp {
         self.status_type = status_type;
     }
     
-    fn execute_com_offset(&mut self) {
-        let config = self.config.clone();
-        
-        match MacroExecutor::new() {
-            Ok(mut executor) => {
-                match executor.execute_com_offset(&config) {
-                    Ok(()) => self.set_status("COM Offset executed successfully", StatusType::Success),
-                    Err(e) => self.set_status(&format!("COM Offset failed: {}", e), StatusType::Error),
+    fn run_macro(&mut self, kind: MacroKind) {
+        self.run_steps(&kind.to_string(), kind.steps());
+    }
+    
+    fn run_steps(&mut self, name: &str, steps: Vec<MacroStep>) {
+        let result = self.worker.submit(name, steps, self.config.clone(), Instant::now());
+        self.report_submit(name, result);
+    }
+    
+    fn report_submit(&mut self, name: &str, result: Result<(), MacroError>) {
+        match result {
+            Ok(()) => self.set_status(&format!("{} queued...", name), StatusType::Info),
+            Err(e) => self.report_error(&format!("{} not started", name), &e),
+        }
+    }
+    
+    /// Shows `error` after `context`, with a hint and severity that depend on what went wrong
+    fn report_error(&mut self, context: &str, error: &MacroError) {
+        let message = format!("{}: {}", context, error::report(error));
+        match error {
+            MacroError::Config(e) if e.field().is_some() => {
+                // The offending setting is already highlighted, validation runs every frame
+                self.set_status(&format!("{} (see the highlighted setting)", message), StatusType::Warning);
+            }
+            MacroError::Input(InputError::Unsupported(_) | InputError::Init { .. }) => {
+                self.backend_failed = true;
+                self.set_status(&format!("{}. Try another input backend", message), StatusType::Error);
+            }
+            MacroError::Busy(_) | MacroError::NotLoaded(_) => self.set_status(&message, StatusType::Warning),
+            _ => self.set_status(&message, StatusType::Error),
+        }
+    }
+    
+    fn run_user_macro(&mut self, name: &str) {
+        match self.user_macros.iter().find(|m| m.name == name) {
+            Some(user_macro) => match user_macro.validate_for(self.config.input_backend) {
+                Ok(()) => {
+                    let steps = user_macro.steps.clone();
+                    self.run_steps(name, steps);
                 }
+                Err(e) => self.report_error(&format!("{} not started", name), &e.into()),
+            },
+            None => self.set_status(&format!("Macro '{}' is not loaded", name), StatusType::Warning),
+        }
+    }
+    
+    /// Reads the macro files again, updating applied hotkeys for macros that appeared or went away
+    fn reload_macros(&mut self) {
+        let Some(dir) = user_macros::macros_dir() else {
+            self.set_status("No config directory found, user macros are unavailable", StatusType::Warning);
+            return;
+        };
+        
+        let loaded = user_macros::load_dir(&dir);
+        self.user_macros = loaded.macros;
+        self.macro_load_errors = loaded.errors;
+        
+        match self.macro_load_errors.len() {
+            0 => self.set_status(&format!("Loaded {} user macro(s)", self.user_macros.len()), StatusType::Info),
+            n => self.set_status(&format!("{} macro file(s) failed to load", n), StatusType::Warning),
+        }
+        self.reapply_hotkeys_if_changed();
+    }
+    
+    fn toggle_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let mut recording = recorder.stop();
+            let dropped = recording.retain_sendable(self.config.input_backend);
+            self.trim_start_ms = 0;
+            self.trim_end_ms = recording.duration().as_millis() as u64;
+            if dropped.is_empty() {
+                self.set_status(&format!("Recorded {} key events", recording.events().len()), StatusType::Success);
+            } else {
+                let names: Vec<String> = dropped.into_iter().map(keys::vk_label).collect();
+                let message = format!(
+                    "Recorded {} key events, left out {} (the {} input backend can't send them)",
+                    recording.events().len(), names.join(", "), self.config.input_backend,
+                );
+                self.set_status(&message, StatusType::Warning);
+            }
+            self.recording = Some(recording);
+            return;
+        }
+        
+        // Keep the record hotkey itself out of the recording (its modifiers are still recorded)
+        let ignored = self.hotkey(&Action::ToggleRecording)
+            .and_then(|binding| binding.rsplit('+').next())
+            .and_then(|key| keys::vk_from_name(key.trim()))
+            .into_iter()
+            .collect();
+        match Recorder::start(ignored) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                self.set_status("Recording... press the record hotkey or Stop to finish", StatusType::Info);
+            }
+            Err(e) => {
+                let hint = match e {
+                    RecorderError::NoKeyboard if cfg!(target_os = "linux") => ". The recorder needs an X11 session",
+                    _ => "",
+                };
+                self.set_status(&format!("Failed to start recording: {}{}", error::report(&e), hint), StatusType::Error);
             }
-            Err(e) => self.set_status(&format!("Failed to initialize macro executor: {}", e), StatusType::Error),
         }
     }
     
-    fn execute_wall_clip(&mut self) {
-        let config = self.config.clone();
+    /// Saves the trimmed recording as a macro file and loads it
+    fn save_recording(&mut self) {
+        let Some(recording) = &self.recording else {
+            return;
+        };
+        
+        let name = self.recording_name.trim().to_string();
+        if self.user_macros.iter().any(|m| m.name == name) {
+            self.set_status(&format!("A macro named '{}' already exists", name), StatusType::Error);
+            return;
+        }
+        
+        let trimmed = recording.trimmed(
+            Duration::from_millis(self.trim_start_ms),
+            Duration::from_millis(self.trim_end_ms),
+        );
+        let user_macro = UserMacro { name, description: String::new(), steps: trimmed.to_steps() };
+        let result = user_macros::macros_dir()
+            .ok_or(UserMacroError::Save(ConfigError::NoConfigDir))
+            .and_then(|dir| user_macros::save(&dir, &user_macro));
         
-        match MacroExecutor::new() {
-            Ok(mut executor) => {
-                match executor.execute_wall_clip(&config) {
-                    Ok(()) => self.set_status("Wall Clip executed successfully", StatusType::Success),
-                    Err(e) => self.set_status(&format!("Wall Clip failed: {}", e), StatusType::Error),
+        match result {
+            Ok(path) => {
+                self.recording = None;
+                self.recording_name.clear();
+                self.reload_macros();
+                self.set_status(&format!("Saved '{}' to {}", user_macro.name, path.display()), StatusType::Success);
+            }
+            // The recording is kept either way; a name problem just needs another name
+            Err(e @ (UserMacroError::EmptyName | UserMacroError::ReservedName(_) | UserMacroError::AlreadyExists(_))) => {
+                self.set_status(&format!("{}, pick another name", error::report(&e)), StatusType::Warning);
+            }
+            Err(e) => self.set_status(&format!("Failed to save macro: {}", error::report(&e)), StatusType::Error),
+        }
+    }
+    
+    /// Nudges the peak delay from a hotkey; auto-save persists it at the end of the frame
+    fn adjust_peak_delay(&mut self, steps: i32) {
+        match self.config.nudge_peak_delay(steps) {
+            Ok(delay) => self.set_status(&format!("Peak delay: {:.3} s", delay), StatusType::Success),
+            Err(e) => self.set_status(&error::report(&e), StatusType::Warning),
+        }
+    }
+    
+    /// Measures timer overshoot on a background thread, it takes a couple of seconds
+    fn start_benchmark(&mut self) {
+        let timer = PrecisionTimer::new(self.config.spin_threshold());
+        let spawned = thread::Builder::new()
+            .name("timer-benchmark".to_string())
+            .spawn(move || timer::benchmark(timer, BENCHMARK_WAIT, BENCHMARK_SAMPLES));
+        match spawned {
+            Ok(handle) => {
+                self.benchmark = Some(handle);
+                self.set_status("Benchmarking timer...", StatusType::Info);
+            }
+            Err(e) => self.set_status(&format!("Failed to start benchmark: {}", e), StatusType::Error),
+        }
+    }
+    
+    fn poll_benchmark(&mut self) {
+        if !self.benchmark.as_ref().is_some_and(JoinHandle::is_finished) {
+            return;
+        }
+        match self.benchmark.take().map(JoinHandle::join) {
+            Some(Ok(report)) => {
+                self.benchmark_report = Some(report);
+                self.set_status("Timer benchmark finished", StatusType::Success);
+            }
+            _ => self.set_status("Timer benchmark failed", StatusType::Error),
+        }
+    }
+    
+    fn abort_macro(&mut self) {
+        if self.worker.abort() {
+            self.set_status("Aborting...", StatusType::Warning);
+        }
+    }
+    
+    fn release_all_keys(&mut self) {
+        let result = self.worker.release_all_keys(&self.config);
+        self.report_release(result);
+    }
+    
+    fn report_release(&mut self, result: Result<(), MacroError>) {
+        match result {
+            Ok(()) => self.set_status("Releasing all keys...", StatusType::Info),
+            Err(e) => self.report_error("Failed to release keys", &e),
+        }
+    }
+    
+    fn handle_worker_events(&mut self) {
+        for mut event in self.worker.poll() {
+            if let WorkerEvent::Finished { latency, timings, .. } = &mut event {
+                self.last_latency = latency.or(self.last_latency);
+                self.last_timings = std::mem::take(timings);
+            }
+            match event {
+                WorkerEvent::Started(name) => self.set_status(&format!("{} running...", name), StatusType::Info),
+                WorkerEvent::Progress(message) => self.set_status(&message, StatusType::Info),
+                WorkerEvent::Finished { name, result: Ok(backend), .. } => {
+                    self.backend_failed = false;
+                    let message = format!("{} executed successfully ({})", name, backend);
+                    self.set_status(&message, StatusType::Success);
                 }
+                WorkerEvent::Finished { name, result: Err(e), .. } => self.report_error(&format!("{} failed", name), &e),
+                WorkerEvent::Aborted(name) => {
+                    self.set_status(&format!("{} aborted, held keys released", name), StatusType::Warning);
+                }
+                WorkerEvent::KeysReleased(Ok(())) => self.set_status("All keys released", StatusType::Success),
+                WorkerEvent::KeysReleased(Err(e)) => self.report_error("Failed to release keys", &e),
+            }
+        }
+    }
+    
+    /// Reports what the hotkey thread did, and runs the actions it leaves to the UI
+    fn handle_dispatch_events(&mut self) {
+        for event in self.dispatcher.poll() {
+            match event {
+                DispatchEvent::Handled { action: Action::Run(kind), result } => self.report_submit(&kind.to_string(), result),
+                DispatchEvent::Handled { action: Action::UserMacro(name), result } => self.report_submit(&name, result),
+                DispatchEvent::Handled { action: Action::Abort, .. } => self.set_status("Aborting...", StatusType::Warning),
+                DispatchEvent::Handled { action: Action::ReleaseAllKeys, result } => self.report_release(result),
+                // Already done on the hotkey thread, running it again here would run it twice
+                DispatchEvent::Handled { action, result } => self.report_submit(&action.to_string(), result),
+                DispatchEvent::Forwarded(action) => self.trigger(action),
             }
-            Err(e) => self.set_status(&format!("Failed to initialize macro executor: {}", e), StatusType::Error),
         }
     }
     
+    /// Every action that can currently be bound, in the order the Hotkeys section lists them
+    fn bindable_actions(&self) -> Vec<Action> {
+        let mut actions = Action::BUILTIN.to_vec();
+        actions.extend(self.user_macros.iter().map(|m| Action::UserMacro(m.name.clone())));
+        actions.extend(self.profiles.names().map(|name| Action::SwitchProfile(name.clone())));
+        actions
+    }
+    
+    /// Bindings to register. Ones for macros or profiles that no longer exist stay in the config but are skipped.
+    fn bound_hotkeys(&self) -> Vec<(Action, String)> {
+        self.bindable_actions().into_iter()
+            .filter_map(|action| {
+                let key = self.hotkey(&action)?.to_string();
+                Some((action, key))
+            })
+            .collect()
+    }
+    
+    fn hotkey(&self, action: &Action) -> Option<&str> {
+        match action {
+            Action::SwitchProfile(name) => self.profiles.switch_hotkey(name),
+            _ => self.config.hotkey(action),
+        }
+    }
+    
+    /// Where the binding for `action` is kept and under which key. Profile switches are shared by
+    /// every profile, everything else belongs to the active one.
+    fn hotkey_entry(&mut self, action: &Action) -> (&mut BTreeMap<String, String>, String) {
+        match action {
+            Action::SwitchProfile(name) => (&mut self.profiles.switch_hotkeys, name.clone()),
+            _ => (&mut self.config.hotkeys, action.id()),
+        }
+    }
+    
+    fn apply_hotkeys(&mut self) -> Result<(), HotkeyError> {
+        let bindings = self.bound_hotkeys();
+        let manager = self.hotkey_manager.as_mut().ok_or(HotkeyError::ManagerUnavailable)?;
+        if let Err(e) = manager.register_hotkeys(&bindings) {
+            self.hotkey_error = e.action().cloned();
+            return Err(e);
+        }
+        self.hotkey_error = None;
+        self.dispatcher.set_bindings(manager.bindings());
+        self.applied_hotkeys = Some(bindings);
+        Ok(())
+    }
+    
     fn register_hotkeys(&mut self) {
-        if let Some(ref mut manager) = self.hotkey_manager {
-            match manager.register_hotkeys(&self.config.com_hotkey, &self.config.clip_hotkey) {
-                Ok(()) => {
-                    self.active_hotkeys = format!("COM: {}, Clip: {}", self.config.com_hotkey, self.config.clip_hotkey);
-                    self.set_status("Hotkeys registered successfully", StatusType::Success);
-                }
-                Err(e) => self.set_status(&format!("Failed to register hotkeys: {}", e), StatusType::Error),
+        match self.apply_hotkeys() {
+            Ok(()) => self.set_status("Hotkeys registered successfully", StatusType::Success),
+            Err(e) => self.set_status(&format!("Hotkeys unchanged: {}", error::report(&e)), StatusType::Error),
+        }
+    }
+    
+    /// Keeps registered hotkeys in step with the active profile once the user has applied them
+    fn reapply_hotkeys_if_changed(&mut self) {
+        let Some(applied) = &self.applied_hotkeys else {
+            return;
+        };
+        if *applied != self.bound_hotkeys() {
+            if let Err(e) = self.apply_hotkeys() {
+                self.set_status(&format!("Hotkeys unchanged: {}", error::report(&e)), StatusType::Error);
             }
-        } else {
-            self.set_status("Hotkey manager not available", StatusType::Error);
+        }
+    }
+    
+    fn trigger(&mut self, action: Action) {
+        match action {
+            Action::Run(kind) => self.run_macro(kind),
+            Action::Abort => self.abort_macro(),
+            Action::ReleaseAllKeys => self.release_all_keys(),
+            Action::ToggleRecording => self.toggle_recording(),
+            Action::IncreasePeakDelay => self.adjust_peak_delay(1),
+            Action::DecreasePeakDelay => self.adjust_peak_delay(-1),
+            Action::UserMacro(name) => self.run_user_macro(&name),
+            Action::SwitchProfile(name) => self.switch_profile(&name),
         }
     }
 }
@@ -92,36 +544,29 @@ impl eframe::App for MacroApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Handle keyboard input for hotkey capture
         ctx.input(|i| {
-            if let Some(capture) = &self.capturing_hotkey {
-                for event in &i.events {
-                    if let egui::Event::Key { key, pressed: true, .. } = event {
-                        let key_name = format!("{:?}", key).to_lowercase();
-                        match capture {
-                            HotkeyCapture::Com => {
-                                self.config.com_hotkey = key_name;
-                                self.set_status("COM hotkey updated", StatusType::Success);
-                            }
-                            HotkeyCapture::Clip => {
-                                self.config.clip_hotkey = key_name;
-                                self.set_status("Clip hotkey updated", StatusType::Success);
-                            }
+            let Some(action) = self.capturing_hotkey.clone() else {
+                return;
+            };
+            for event in &i.events {
+                if let egui::Event::Key { key, pressed: true, modifiers, .. } = event {
+                    match hotkeys::binding_from_egui(*modifiers, *key, recorder::logo_key_down()) {
+                        Ok(key_name) => {
+                            let (hotkeys, key) = self.hotkey_entry(&action);
+                            hotkeys.insert(key, key_name);
+                            self.set_status(&format!("{} hotkey updated", action), StatusType::Success);
+                            self.capturing_hotkey = None;
+                            return;
                         }
-                        self.capturing_hotkey = None;
-                        return;
+                        // Stay in capture mode so another key can be tried
+                        Err(e) => self.set_status(&format!("{}, press another key", e), StatusType::Warning),
                     }
                 }
             }
         });
-
-        // Check for hotkey events
-        if let Some(ref manager) = self.hotkey_manager {
-            for event in manager.check_events() {
-                match event {
-                    HotkeyEvent::ComOffset => self.execute_com_offset(),
-                    HotkeyEvent::WallClip => self.execute_wall_clip(),
-                }
-            }
-        }
+        
+        self.handle_dispatch_events();
+        self.poll_benchmark();
+        self.handle_worker_events();
         
         // Minimalist dark and white color scheme
         let _bg_color = egui::Color32::from_rgb(0, 0, 0);  // Pure black
@@ -130,144 +575,479 @@ impl eframe::App for MacroApp {
         let error_color = egui::Color32::from_rgb(255, 0, 0);  // Red
         let warning_color = egui::Color32::from_rgb(255, 255, 0);  // Yellow
         
+        // Settings that would stop a macro from running are shown in red
+        let invalid_field = self.config.validate_all().err().and_then(|e| e.field());
+        let field_label = move |text: &str, field: ConfigField| {
+            let text = egui::RichText::new(text);
+            if invalid_field == Some(field) { text.color(error_color) } else { text }
+        };
+        
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                // Title
-                ui.add_space(20.0);
-                ui.heading(egui::RichText::new("Moonwalk Macros")
-                    .size(24.0)
-                    .color(accent_color)
-                    .strong());
-                ui.add_space(20.0);
-                
-                // Settings Section
-                ui.group(|ui| {
-                    ui.set_min_width(440.0);
-                    ui.label(egui::RichText::new("Settings").size(14.0).strong());
-                    ui.separator();
-                    
-                    ui.horizontal(|ui| {
-                        ui.label("Shiftlock Key:");
-                        ui.radio_value(&mut self.config.shiftlock_key, ShiftlockKey::Shift, "Left Shift");
-                        ui.radio_value(&mut self.config.shiftlock_key, ShiftlockKey::Control, "Control");
-                    });
-                    
-                    ui.horizontal(|ui| {
-                        ui.label("Moonwalk Emote Slot (1-8):");
-                        ui.add(egui::DragValue::new(&mut self.config.emote_slot)
-                            .range(1..=8)
-                            .speed(0.1));
-                    });
-                    
-                    ui.horizontal(|ui| {
-                        ui.label("Gear/Item Slot (1-9,0):");
-                        ui.add(egui::TextEdit::singleline(&mut self.config.gear_slot)
-                            .desired_width(40.0)
-                            .char_limit(1));
-                    });
+            // Settings sections can be expanded past the window height
+            egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    // Title
+                    ui.add_space(20.0);
+                    ui.heading(egui::RichText::new("Moonwalk Macros")
+                        .size(24.0)
+                        .color(accent_color)
+                        .strong());
+                    ui.add_space(20.0);
                     
-                    ui.horizontal(|ui| {
-                        ui.label("Peak Delay (seconds):");
-                        ui.add(egui::DragValue::new(&mut self.config.peak_delay)
-                            .range(0.05..=5.0)
-                            .speed(0.01)
-                            .fixed_decimals(2));
+                    // Settings Section
+                    ui.group(|ui| {
+                        ui.set_min_width(440.0);
+                        ui.label(egui::RichText::new("Settings").size(14.0).strong());
+                        ui.separator();
+                        
+                        ui.horizontal(|ui| {
+                            ui.label("Profile:");
+                            let mut selected = self.profiles.active.clone();
+                            egui::ComboBox::from_id_source("active_profile")
+                                .selected_text(&selected)
+                                .width(160.0)
+                                .show_ui(ui, |ui| {
+                                    for name in self.profiles.names() {
+                                        ui.selectable_value(&mut selected, name.clone(), name);
+                                    }
+                                });
+                            if selected != self.profiles.active {
+                                self.switch_profile(&selected);
+                            }
+                            
+                            if ui.button("Delete").clicked() {
+                                self.edit_profiles("Profile deleted", |profiles, _| profiles.delete_active());
+                            }
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut self.profile_name_input)
+                                .hint_text("Profile name")
+                                .desired_width(160.0));
+                            
+                            if ui.button("New").clicked() {
+                                self.edit_profiles("Profile created", ProfileStore::create);
+                            }
+                            if ui.button("Duplicate").clicked() {
+                                self.edit_profiles("Profile duplicated", ProfileStore::duplicate);
+                            }
+                            if ui.button("Rename").clicked() {
+                                self.edit_profiles("Profile renamed", ProfileStore::rename_active);
+                            }
+                        });
+                        
+                        ui.separator();
+                        
+                        ui.horizontal(|ui| {
+                            let label = egui::RichText::new("Input Backend:");
+                            ui.label(if self.backend_failed { label.color(error_color) } else { label });
+                            let before = self.config.input_backend;
+                            egui::ComboBox::from_id_source("input_backend")
+                                .selected_text(self.config.input_backend.to_string())
+                                .show_ui(ui, |ui| {
+                                    for kind in InputBackendKind::ALL.into_iter().filter(|k| k.is_supported()) {
+                                        ui.selectable_value(&mut self.config.input_backend, kind, kind.to_string());
+                                    }
+                                });
+                            if self.config.input_backend != before {
+                                self.backend_failed = false;
+                            }
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.label(field_label("Moonwalk Emote Slot (1-8):", ConfigField::EmoteSlot));
+                            ui.add(egui::DragValue::new(&mut self.config.emote_slot)
+                                .range(1..=8)
+                                .speed(0.1));
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.label(field_label("Gear/Item Slot (1-9,0):", ConfigField::GearSlot));
+                            ui.add(egui::TextEdit::singleline(&mut self.config.gear_slot)
+                                .desired_width(40.0)
+                                .char_limit(1));
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.label(field_label("Peak Delay (seconds):", ConfigField::PeakDelay));
+                            ui.add(egui::DragValue::new(&mut self.config.peak_delay)
+                                .range(config::MIN_PEAK_DELAY..=config::MAX_PEAK_DELAY)
+                                .speed(0.01)
+                                .fixed_decimals(3));
+                            ui.label(field_label("Hotkey step:", ConfigField::PeakDelayStep));
+                            ui.add(egui::DragValue::new(&mut self.config.peak_delay_step)
+                                .range(config::MIN_PEAK_DELAY_STEP..=config::MAX_PEAK_DELAY_STEP)
+                                .speed(0.001)
+                                .fixed_decimals(3));
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.unequip_after, "Unequip item after");
+                            ui.add_space(20.0);
+                            ui.checkbox(&mut self.config.unshiftlock_after, "Unshiftlock after wall clip");
+                        });
+                        
+                        egui::CollapsingHeader::new(field_label("Game keys", ConfigField::Keymap)).id_source("game_keys").show(ui, |ui| {
+                            ui.label("The keys you use in-game for each action, macros press these");
+                            let backend = self.config.input_backend;
+                            for (label, vk_code) in self.config.keymap.fields_mut() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{}:", label));
+                                    egui::ComboBox::from_id_source(("game_key", label))
+                                        .selected_text(keys::vk_label(*vk_code))
+                                        .height(240.0)
+                                        .show_ui(ui, |ui| {
+                                            for (name, vk) in keys::vk_names().filter(|(_, vk)| backend.can_send(*vk)) {
+                                                ui.selectable_value(vk_code, vk, name);
+                                            }
+                                        });
+                                });
+                            }
+                            if let Err(e) = self.config.validate_keymap() {
+                                ui.label(egui::RichText::new(e.to_string()).color(warning_color));
+                            }
+                        });
+                        
+                        egui::CollapsingHeader::new(field_label("Wall Clip movement", ConfigField::WallClipMovement)).id_source("wall_clip_movement").show(ui, |ui| {
+                            ui.label("Keys held during the clip, each from its start (ms after shiftlock) for its hold time");
+                            let mut remove = None;
+                            let can_remove = self.config.wall_clip_movement.len() > 1;
+                            for (i, movement) in self.config.wall_clip_movement.iter_mut().enumerate() {
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_id_source(("movement_key", i))
+                                        .selected_text(movement_label(movement.key))
+                                        .show_ui(ui, |ui| {
+                                            for (key, label) in config::MOVEMENT_KEYS {
+                                                ui.selectable_value(&mut movement.key, key, label);
+                                            }
+                                        });
+                                    ui.label("start:");
+                                    ui.add(egui::DragValue::new(&mut movement.start_ms)
+                                        .range(0..=config::MAX_MOVEMENT_START_MS)
+                                        .speed(1.0)
+                                        .suffix(" ms"));
+                                    ui.label("hold:");
+                                    ui.add(egui::DragValue::new(&mut movement.hold_ms)
+                                        .range(config::MOVEMENT_HOLD_MS)
+                                        .speed(1.0)
+                                        .suffix(" ms"));
+                                    if ui.add_enabled(can_remove, egui::Button::new("Remove")).clicked() {
+                                        remove = Some(i);
+                                    }
+                                });
+                            }
+                            if let Some(i) = remove {
+                                self.config.wall_clip_movement.remove(i);
+                            }
+                            
+                            ui.horizontal(|ui| {
+                                let unused = config::MOVEMENT_KEYS.iter()
+                                    .map(|(key, _)| *key)
+                                    .find(|key| self.config.wall_clip_movement.iter().all(|m| m.key != *key));
+                                if ui.add_enabled(unused.is_some(), egui::Button::new("Add key")).clicked() {
+                                    if let Some(key) = unused {
+                                        self.config.wall_clip_movement.push(MovementKey { key, start_ms: 0, hold_ms: 250 });
+                                    }
+                                }
+                                ui.label("Presets:");
+                                for (name, preset) in MOVEMENT_PRESETS {
+                                    if ui.button(name).clicked() {
+                                        self.config.wall_clip_movement = preset.to_vec();
+                                    }
+                                }
+                            });
+                            if let Err(e) = self.config.validate_wall_clip_movement() {
+                                ui.label(egui::RichText::new(e.to_string()).color(warning_color));
+                            }
+                        });
+                        
+                        egui::CollapsingHeader::new(field_label("Advanced timing", ConfigField::Timing)).id_source("advanced_timing").show(ui, |ui| {
+                            for (label, range, value) in self.config.timing.fields_mut() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{} (ms):", label));
+                                    ui.add(egui::DragValue::new(value).range(range).speed(1.0));
+                                });
+                            }
+                            if ui.button("Reset to defaults").clicked() {
+                                self.config.timing = StepTimings::default();
+                            }
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            ui.label(field_label("Spin threshold (µs):", ConfigField::SpinThreshold));
+                            ui.add(egui::DragValue::new(&mut self.config.spin_threshold_us)
+                                .range(0..=config::MAX_SPIN_THRESHOLD_US)
+                                .speed(50.0))
+                                .on_hover_text("The last part of each wait is spun on the CPU instead of slept, for precise timing. 0 only sleeps.");
+                            
+                            let running = self.benchmark.is_some();
+                            let label = if running { "Benchmarking..." } else { "Benchmark Timer" };
+                            if ui.add_enabled(!running, egui::Button::new(label)).clicked() {
+                                self.start_benchmark();
+                            }
+                        });
+                        
+                        if let Some(report) = &self.benchmark_report {
+                            let title = format!("Timer overshoot ({} waits of {} ms)", BENCHMARK_SAMPLES, report.wait.as_millis());
+                            egui::CollapsingHeader::new(title).id_source("timer_benchmark").show(ui, |ui| {
+                                for (name, stats) in [("sleep", &report.sleep), ("hybrid", &report.hybrid)] {
+                                    ui.label(egui::RichText::new(format!("{:<7}{}", name, summarize(stats))).monospace());
+                                }
+                                
+                                ui.label(egui::RichText::new(format!("{:<10}{:>7}{:>8}", "overshoot", "sleep", "hybrid")).monospace());
+                                let sleep = report.sleep.histogram();
+                                let hybrid = report.hybrid.histogram();
+                                for (i, (sleep, hybrid)) in sleep.iter().zip(&hybrid).enumerate() {
+                                    let bucket = match timer::HISTOGRAM_BUCKETS.get(i) {
+                                        Some(bound) => format!("< {}", format_duration(*bound)),
+                                        None => format!(">= {}", format_duration(timer::HISTOGRAM_BUCKETS[i - 1])),
+                                    };
+                                    ui.label(egui::RichText::new(format!("{:<10}{:>7}{:>8}", bucket, sleep, hybrid)).monospace());
+                                }
+                            });
+                        }
                     });
                     
-                    ui.horizontal(|ui| {
-                        ui.checkbox(&mut self.config.unequip_after, "Unequip item after");
-                        ui.add_space(20.0);
-                        ui.checkbox(&mut self.config.unshiftlock_after, "Unshiftlock after wall clip");
-                    });
-                });
-                
-                ui.add_space(15.0);
-                
-                // Hotkeys Section
-                ui.group(|ui| {
-                    ui.set_min_width(440.0);
-                    ui.label(egui::RichText::new("Hotkeys").size(14.0).strong());
-                    ui.separator();
+                    ui.add_space(15.0);
                     
-                    ui.horizontal(|ui| {
-                        ui.label("COM Hotkey:");
-                        ui.add(egui::TextEdit::singleline(&mut self.config.com_hotkey)
-                            .desired_width(80.0));
+                    // Hotkeys Section
+                    ui.group(|ui| {
+                        ui.set_min_width(440.0);
+                        ui.label(egui::RichText::new("Hotkeys").size(14.0).strong());
+                        ui.separator();
                         
-                        if ui.button("Set").clicked() {
-                            self.capturing_hotkey = Some(HotkeyCapture::Com);
-                            self.set_status("Press any key to set COM hotkey...", StatusType::Info);
-                        }
+                        egui::ScrollArea::vertical().id_source("hotkeys").max_height(180.0).show(ui, |ui| {
+                            for action in self.bindable_actions() {
+                                ui.horizontal(|ui| {
+                                    let label = egui::RichText::new(format!("{}:", action));
+                                    ui.label(if self.hotkey_error.as_ref() == Some(&action) { label.color(error_color) } else { label });
+                                    let (hotkeys, id) = self.hotkey_entry(&action);
+                                    let mut key = hotkeys.get(&id).cloned().unwrap_or_default();
+                                    if ui.add(egui::TextEdit::singleline(&mut key).desired_width(120.0)).changed() {
+                                        if key.trim().is_empty() {
+                                            hotkeys.remove(&id);
+                                        } else {
+                                            hotkeys.insert(id, key);
+                                        }
+                                    }
+                                    
+                                    if ui.button("Set").clicked() {
+                                        self.set_status(&format!("Press any key to set {} hotkey...", action), StatusType::Info);
+                                        self.capturing_hotkey = Some(action);
+                                    }
+                                });
+                            }
+                        });
+                        
+                        ui.horizontal(|ui| {
+                            if ui.button("Apply Hotkeys").clicked() {
+                                self.register_hotkeys();
+                            }
+                            let active = match &self.applied_hotkeys {
+                                Some(bindings) if !bindings.is_empty() => bindings.iter()
+                                    .map(|(action, key)| format!("{}: {}", action, key))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                                _ => "None".to_string(),
+                            };
+                            ui.add(egui::Label::new(format!("Active: {}", active)).wrap());
+                        });
                     });
                     
-                    ui.horizontal(|ui| {
-                        ui.label("Clip Hotkey:");
-                        ui.add(egui::TextEdit::singleline(&mut self.config.clip_hotkey)
-                            .desired_width(80.0));
+                    ui.add_space(15.0);
+                    
+                    // Action Buttons
+                    ui.group(|ui| {
+                        ui.set_min_width(440.0);
+                        ui.label(egui::RichText::new("Actions").size(14.0).strong());
+                        ui.separator();
                         
-                        if ui.button("Set").clicked() {
-                            self.capturing_hotkey = Some(HotkeyCapture::Clip);
-                            self.set_status("Press any key to set Clip hotkey...", StatusType::Info);
-                        }
+                        ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                            let idle = self.worker.running().is_none();
+                            ui.horizontal(|ui| {
+                                ui.add_space((440.0 - 380.0) / 2.0); // Center the buttons
+                                let com_button = egui::Button::new("Run COM Offset");
+                                if ui.add_enabled_ui(idle, |ui| ui.add_sized([180.0, 40.0], com_button)).inner.clicked() {
+                                    self.run_macro(MacroKind::ComOffset);
+                                }
+                                
+                                ui.add_space(20.0);
+                                
+                                let clip_button = egui::Button::new("Run Wall Clip");
+                                if ui.add_enabled_ui(idle, |ui| ui.add_sized([180.0, 40.0], clip_button)).inner.clicked() {
+                                    self.run_macro(MacroKind::WallClip);
+                                }
+                            });
+                            
+                            ui.add_space(10.0);
+                            
+                            // User macros, several buttons per row
+                            let mut clicked_macro = None;
+                            ui.horizontal_wrapped(|ui| {
+                                for user_macro in &self.user_macros {
+                                    let button = egui::Button::new(&user_macro.name);
+                                    let response = ui.add_enabled_ui(idle, |ui| ui.add_sized([140.0, 30.0], button)).inner;
+                                    let response = if user_macro.description.is_empty() {
+                                        response
+                                    } else {
+                                        response.on_hover_text(&user_macro.description)
+                                    };
+                                    if response.clicked() {
+                                        clicked_macro = Some(user_macro.name.clone());
+                                    }
+                                }
+                                
+                                if ui.add_sized([140.0, 30.0], egui::Button::new("Reload Macros")).clicked() {
+                                    self.reload_macros();
+                                }
+                            });
+                            if let Some(name) = clicked_macro {
+                                self.run_user_macro(&name);
+                            }
+                            for LoadError { file_name, error } in &self.macro_load_errors {
+                                let message = format!("{}: {}", file_name, error::report(error));
+                                ui.label(egui::RichText::new(message).color(warning_color));
+                            }
+                            
+                            ui.add_space(10.0);
+                            
+                            ui.horizontal(|ui| {
+                                ui.add_space((440.0 - 380.0) / 2.0);
+                                let abort_button = egui::Button::new("Abort");
+                                if ui.add_enabled_ui(!idle, |ui| ui.add_sized([100.0, 30.0], abort_button)).inner.clicked() {
+                                    self.abort_macro();
+                                }
+                                
+                                ui.add_space(20.0);
+                                
+                                if ui.add_sized([140.0, 30.0], egui::Button::new("Release All Keys")).clicked() {
+                                    self.release_all_keys();
+                                }
+                                
+                                ui.add_space(20.0);
+                                
+                                if ui.add_sized([100.0, 30.0], egui::Button::new("Quit")).clicked() {
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                                }
+                            });
+                        });
                     });
                     
-                    ui.horizontal(|ui| {
-                        if ui.button("Apply Hotkeys").clicked() {
-                            self.register_hotkeys();
-                        }
-                        ui.label(format!("Active: {}", self.active_hotkeys));
-                    });
-                });
-                
-                ui.add_space(15.0);
-                
-                // Action Buttons
-                ui.group(|ui| {
-                    ui.set_min_width(440.0);
-                    ui.label(egui::RichText::new("Actions").size(14.0).strong());
-                    ui.separator();
+                    ui.add_space(15.0);
                     
-                    ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    // Recorder Section
+                    ui.group(|ui| {
+                        ui.set_min_width(440.0);
+                        ui.label(egui::RichText::new("Recorder").size(14.0).strong());
+                        ui.separator();
+                        
                         ui.horizontal(|ui| {
-                            ui.add_space((440.0 - 380.0) / 2.0); // Center the buttons
-                            if ui.add_sized([180.0, 40.0], egui::Button::new("Run COM Offset")).clicked() {
-                                self.execute_com_offset();
+                            let label = if self.recorder.is_some() { "Stop Recording" } else { "Start Recording" };
+                            if ui.button(label).clicked() {
+                                self.toggle_recording();
                             }
-                            
-                            ui.add_space(20.0);
-                            
-                            if ui.add_sized([180.0, 40.0], egui::Button::new("Run Wall Clip")).clicked() {
-                                self.execute_wall_clip();
+                            if let Some(recorder) = &self.recorder {
+                                ui.label(format!("Recording... {} key events", recorder.events().len()));
                             }
                         });
                         
-                        ui.add_space(10.0);
-                        
-                        if ui.add_sized([100.0, 30.0], egui::Button::new("Quit")).clicked() {
-                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        let mut save = false;
+                        let mut discard = false;
+                        if let Some(recording) = &self.recording {
+                            let duration_ms = recording.duration().as_millis() as u64;
+                            ui.horizontal(|ui| {
+                                ui.label("Keep from");
+                                ui.add(egui::DragValue::new(&mut self.trim_start_ms)
+                                    .range(0..=self.trim_end_ms)
+                                    .suffix(" ms"));
+                                ui.label("to");
+                                ui.add(egui::DragValue::new(&mut self.trim_end_ms)
+                                    .range(self.trim_start_ms..=duration_ms)
+                                    .suffix(" ms"));
+                            });
+                            
+                            // Timeline, with trimmed-away events greyed out
+                            egui::ScrollArea::vertical().max_height(100.0).show(ui, |ui| {
+                                for event in recording.events() {
+                                    let at_ms = event.at.as_millis() as u64;
+                                    let action = if event.pressed { "down" } else { "up" };
+                                    let color = if (self.trim_start_ms..=self.trim_end_ms).contains(&at_ms) {
+                                        egui::Color32::WHITE
+                                    } else {
+                                        egui::Color32::DARK_GRAY
+                                    };
+                                    let text = format!("{:>7} ms  {} {}", at_ms, KeyRef::Vk(event.vk), action);
+                                    ui.label(egui::RichText::new(text).monospace().color(color));
+                                }
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut self.recording_name)
+                                    .hint_text("Macro name")
+                                    .desired_width(160.0));
+                                save = ui.button("Save as Macro").clicked();
+                                discard = ui.button("Discard").clicked();
+                            });
+                        }
+                        if save {
+                            self.save_recording();
+                        }
+                        if discard {
+                            self.recording = None;
                         }
                     });
-                });
-                
-                ui.add_space(20.0);
-                
-                // Status Bar
-                ui.separator();
-                ui.horizontal(|ui| {
-                    let status_color = match self.status_type {
-                        StatusType::Success => success_color,
-                        StatusType::Error => error_color,
-                        StatusType::Warning => warning_color,
-                        StatusType::Info => egui::Color32::WHITE,
-                    };
                     
-                    ui.label(egui::RichText::new("Status:").strong());
-                    ui.label(egui::RichText::new(&self.status_message).color(status_color));
+                    ui.add_space(20.0);
+                    
+                    // Status Bar
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let status_color = match self.status_type {
+                            StatusType::Success => success_color,
+                            StatusType::Error => error_color,
+                            StatusType::Warning => warning_color,
+                            StatusType::Info => egui::Color32::WHITE,
+                        };
+                        
+                        ui.label(egui::RichText::new("Status:").strong());
+                        ui.label(egui::RichText::new(&self.status_message).color(status_color));
+                    });
+                    if let Some(latency) = self.last_latency {
+                        ui.label(format!("Trigger to first input: {:.1} ms", latency.as_secs_f64() * 1000.0));
+                    }
+                    if let Some(worst) = self.last_timings.iter().map(|t| t.late).max() {
+                        let title = format!("Step timing (worst {:.1} ms late)", worst.as_secs_f64() * 1000.0);
+                        egui::CollapsingHeader::new(title).id_source("step_timing").show(ui, |ui| {
+                            for timing in &self.last_timings {
+                                let text = format!(
+                                    "{:>6} ms  +{:>5.1} ms  {}",
+                                    timing.due.as_millis(), timing.late.as_secs_f64() * 1000.0, timing.step
+                                );
+                                ui.label(egui::RichText::new(text).monospace());
+                            }
+                        });
+                    }
                 });
             });
         });
         
-        // Request repaint for hotkey checking
-        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        self.auto_save(ctx);
+        // Hand the hotkey thread whatever changed this frame
+        self.dispatcher.sync(&self.config, &self.user_macros);
+        
+        // Hotkeys and the worker wake the UI themselves, this only keeps the recorder's live count moving
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+}
+
+impl Drop for MacroApp {
+    fn drop(&mut self) {
+        // Changes made within `SAVE_DELAY` of quitting haven't been written yet
+        self.profiles.set_active_config(self.config.clone());
+        if !self.saving_disabled && self.profiles != self.saved_profiles {
+            if let Err(e) = self.save_profiles() {
+                eprintln!("Failed to save settings: {}", error::report(&e));
+            }
+        }
     }
 }
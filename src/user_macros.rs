@@ -0,0 +1,339 @@
+// Macros defined by the user as JSON files in the config directory
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config;
+use crate::error::UserMacroError;
+use crate::input::{InputBackendKind, MouseButton};
+use crate::macros::MacroKind;
+use crate::steps::{KeyRef, MacroStep};
+
+const MACROS_DIR_NAME: &str = "macros";
+/// Longest single wait a macro file may contain
+pub const MAX_WAIT_MS: u64 = 60_000;
+/// Most wheel notches a single scroll step may turn, either way
+pub const MAX_SCROLL_NOTCHES: i32 = 100;
+/// Largest relative mouse movement a single step may make on each axis, either way
+pub const MAX_MOUSE_MOVE: i32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserMacro {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub steps: Vec<MacroStep>,
+}
+
+/// A macro file before its steps are parsed, so step errors can say which step they belong to
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMacro {
+    name: String,
+    #[serde(default)]
+    description: String,
+    steps: Vec<serde_json::Value>,
+}
+
+/// A macro file that didn't load
+#[derive(Debug)]
+pub struct LoadError {
+    pub file_name: String,
+    pub error: UserMacroError,
+}
+
+/// Result of scanning the macros directory: every macro that loaded, plus one error per file that didn't
+#[derive(Debug, Default)]
+pub struct LoadedMacros {
+    pub macros: Vec<UserMacro>,
+    pub errors: Vec<LoadError>,
+}
+
+pub fn macros_dir() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(MACROS_DIR_NAME))
+}
+
+/// Loads every `*.json` file in `dir`, sorted by file name. A missing directory just means no macros.
+pub fn load_dir(dir: &Path) -> LoadedMacros {
+    let mut loaded = LoadedMacros::default();
+    
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(_) => return loaded,
+    };
+    paths.sort();
+    
+    for path in paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let result = fs::read_to_string(&path)
+            .map_err(UserMacroError::Read)
+            .and_then(|source| parse(&source))
+            .and_then(|user_macro| {
+                if loaded.macros.iter().any(|m| m.name == user_macro.name) {
+                    return Err(UserMacroError::DuplicateName(user_macro.name));
+                }
+                Ok(user_macro)
+            });
+        
+        match result {
+            Ok(user_macro) => loaded.macros.push(user_macro),
+            Err(error) => loaded.errors.push(LoadError { file_name, error }),
+        }
+    }
+    
+    loaded
+}
+
+/// Writes `user_macro` to a new file in `dir` named after the macro, refusing to overwrite
+pub fn save(dir: &Path, user_macro: &UserMacro) -> Result<PathBuf, UserMacroError> {
+    user_macro.validate()?;
+    
+    let stem: String = user_macro.name.trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let stem = match stem.trim_matches('-') {
+        "" => "macro",
+        stem => stem,
+    };
+    
+    let path = dir.join(format!("{}.json", stem));
+    if path.exists() {
+        return Err(UserMacroError::AlreadyExists(path));
+    }
+    config::write_json(&path, user_macro)?;
+    Ok(path)
+}
+
+/// Parses and validates one macro file. Syntax errors carry serde's line and column,
+/// problems inside a step carry its index.
+pub fn parse(source: &str) -> Result<UserMacro, UserMacroError> {
+    let raw: RawMacro = serde_json::from_str(source).map_err(UserMacroError::Syntax)?;
+    let steps = raw.steps.into_iter().enumerate()
+        .map(|(index, step)| serde_json::from_value(step).map_err(|source| UserMacroError::Step { index, source }))
+        .collect::<Result<Vec<MacroStep>, _>>()?;
+    
+    let user_macro = UserMacro { name: raw.name, description: raw.description, steps };
+    user_macro.validate()?;
+    Ok(user_macro)
+}
+
+impl UserMacro {
+    pub fn validate(&self) -> Result<(), UserMacroError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(UserMacroError::EmptyName);
+        }
+        if [MacroKind::ComOffset, MacroKind::WallClip].iter().any(|kind| kind.to_string() == name) {
+            return Err(UserMacroError::ReservedName(name.to_string()));
+        }
+        if self.steps.is_empty() {
+            return Err(UserMacroError::NoSteps);
+        }
+        
+        validate_steps(&self.steps, "steps", &mut Held::default(), None)
+    }
+    
+    /// Checks that every fixed key can be sent by `backend`, like the keymap check in the config
+    pub fn validate_for(&self, backend: InputBackendKind) -> Result<(), UserMacroError> {
+        validate_steps(&self.steps, "steps", &mut Held::default(), Some(backend))
+    }
+}
+
+/// Keys and mouse buttons a step list has pressed but not yet released
+#[derive(Clone, Default)]
+struct Held {
+    keys: Vec<KeyRef>,
+    buttons: Vec<MouseButton>,
+}
+
+/// Checks each step, reporting problems with their path such as `steps[2].steps[0].ms`.
+/// Fixed keys are only checked when the `backend` that will send them is known.
+fn validate_steps(
+    steps: &[MacroStep],
+    path: &str,
+    held: &mut Held,
+    backend: Option<InputBackendKind>,
+) -> Result<(), UserMacroError> {
+    for (i, step) in steps.iter().enumerate() {
+        let path = format!("{}[{}]", path, i);
+        if let (
+            Some(backend),
+            MacroStep::KeyPress { key: key @ KeyRef::Vk(vk) } | MacroStep::KeyDown { key: key @ KeyRef::Vk(vk) } | MacroStep::KeyUp { key: key @ KeyRef::Vk(vk) },
+        ) = (backend, step) {
+            if !backend.can_send(*vk) {
+                return Err(UserMacroError::Unsendable { path, key: *key, backend });
+            }
+        }
+        match step {
+            MacroStep::Wait { ms } if *ms > MAX_WAIT_MS => {
+                return Err(UserMacroError::WaitTooLong { path, ms: *ms });
+            }
+            MacroStep::Scroll { notches } if !(-MAX_SCROLL_NOTCHES..=MAX_SCROLL_NOTCHES).contains(notches) => {
+                let path = format!("{}.notches", path);
+                return Err(UserMacroError::OutOfRange { path, value: *notches, max: MAX_SCROLL_NOTCHES });
+            }
+            MacroStep::MouseMove { dx, dy } => {
+                for (field, value) in [("dx", *dx), ("dy", *dy)] {
+                    if !(-MAX_MOUSE_MOVE..=MAX_MOUSE_MOVE).contains(&value) {
+                        let path = format!("{}.{}", path, field);
+                        return Err(UserMacroError::OutOfRange { path, value, max: MAX_MOUSE_MOVE });
+                    }
+                }
+            }
+            MacroStep::KeyDown { key } => held.keys.push(*key),
+            MacroStep::KeyUp { key } => {
+                let Some(index) = held.keys.iter().position(|k| k == key) else {
+                    return Err(UserMacroError::UnheldKey { path, key: *key });
+                };
+                held.keys.remove(index);
+            }
+            MacroStep::MouseDown { button } => held.buttons.push(*button),
+            MacroStep::MouseUp { button } => {
+                let Some(index) = held.buttons.iter().position(|b| b == button) else {
+                    return Err(UserMacroError::UnheldButton { path, button: *button });
+                };
+                held.buttons.remove(index);
+            }
+            MacroStep::Conditional { steps, .. } => {
+                // A branch may or may not run, so it can only release what it held itself
+                let mut branch_held = held.clone();
+                validate_steps(steps, &format!("{}.steps", path), &mut branch_held, backend)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    
+    fn parse_error(source: &str) -> String {
+        error::report(&parse(source).unwrap_err())
+    }
+    
+    #[test]
+    fn parses_valid_macro() {
+        let source = r#"{
+            "name": "Double Equip",
+            "steps": [
+                {"type": "key_press", "key": "gear_slot"},
+                {"type": "wait", "ms": 30},
+                {"type": "key_down", "key": "w"},
+                {"type": "conditional", "flag": "unequip_after", "steps": [{"type": "key_press", "key": "gear_slot"}]},
+                {"type": "key_up", "key": "w"}
+            ]
+        }"#;
+        
+        let user_macro = parse(source).unwrap();
+        assert_eq!(user_macro.name, "Double Equip");
+        assert_eq!(user_macro.steps.len(), 5);
+    }
+    
+    #[test]
+    fn syntax_errors_report_line_and_column() {
+        let source = "{\n  \"name\": \"Broken\",\n  \"steps\": [\n    {\"type\": \"wait\", \"ms\": 5,}\n  ]\n}";
+        let error = parse(source).unwrap_err();
+        assert!(matches!(error, UserMacroError::Syntax(_)));
+        assert!(error.to_string().contains("line 4"), "{}", error);
+    }
+    
+    #[test]
+    fn step_errors_report_step_index() {
+        let source = r#"{"name": "Bad Key", "steps": [{"type": "wait", "ms": 5}, {"type": "key_press", "key": "hyper"}]}"#;
+        assert!(matches!(parse(source), Err(UserMacroError::Step { index: 1, .. })));
+        assert_eq!(parse_error(source), "steps[1]: unknown key 'hyper'");
+    }
+    
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let error = parse_error(r#"{"name": "Typo", "stpes": []}"#);
+        assert!(error.contains("unknown field `stpes`"), "{}", error);
+    }
+    
+    #[test]
+    fn validation_errors_name_the_field() {
+        let long_wait = r#"{"name": "Slow", "steps": [{"type": "conditional", "flag": "unequip_after",
+            "steps": [{"type": "wait", "ms": 999999}]}]}"#;
+        assert_eq!(parse_error(long_wait), "steps[0].steps[0].ms: 999999 ms is longer than the 60000 ms limit");
+        
+        let spin = r#"{"name": "Spin", "steps": [{"type": "scroll", "notches": -2147483648}]}"#;
+        assert_eq!(parse_error(spin), "steps[0].notches: -2147483648 is outside the range of -100 to 100");
+        
+        let flick = r#"{"name": "Flick", "steps": [{"type": "mouse_move", "dx": 20, "dy": 2147483647}]}"#;
+        assert_eq!(parse_error(flick), "steps[0].dy: 2147483647 is outside the range of -10000 to 10000");
+        
+        let stray_up = r#"{"name": "Stray", "steps": [{"type": "key_up", "key": "w"}]}"#;
+        assert_eq!(parse_error(stray_up), "steps[0].key: 'w' is released without being held by an earlier key_down");
+        
+        let stray_button = r#"{"name": "Stray", "steps": [{"type": "mouse_down", "button": "left"},
+            {"type": "mouse_up", "button": "right"}]}"#;
+        assert_eq!(parse_error(stray_button), "steps[1].button: 'right' is released without being held by an earlier mouse_down");
+        
+        let reserved = r#"{"name": "Wall Clip", "steps": [{"type": "wait", "ms": 1}]}"#;
+        assert!(matches!(parse(reserved), Err(UserMacroError::ReservedName(name)) if name == "Wall Clip"));
+        
+        let empty = r#"{"name": "Empty", "steps": []}"#;
+        assert!(matches!(parse(empty), Err(UserMacroError::NoSteps)));
+    }
+    
+    #[test]
+    fn keys_are_checked_against_the_backend() {
+        // F13 has no enigo mapping, but SendInput passes any virtual-key code through
+        let source = r#"{"name": "F13", "steps": [{"type": "key_press", "key": "w"},
+            {"type": "conditional", "flag": "unequip_after", "steps": [{"type": "key_down", "key": "f13"}]}]}"#;
+        let user_macro = parse(source).unwrap();
+        
+        let error = user_macro.validate_for(InputBackendKind::Enigo).unwrap_err();
+        assert_eq!(error.to_string(), "steps[1].steps[0].key: 'f13' can't be sent by the enigo input backend");
+        assert!(user_macro.validate_for(InputBackendKind::SendInput).is_ok());
+    }
+    
+    #[test]
+    fn loads_directory_and_collects_errors() {
+        let dir = std::env::temp_dir().join(format!("moonwalk-macros-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.json"), r#"{"name": "Tap W", "steps": [{"type": "key_press", "key": "w"}]}"#).unwrap();
+        fs::write(dir.join("b.json"), r#"{"name": "Tap W", "steps": [{"type": "key_press", "key": "s"}]}"#).unwrap();
+        fs::write(dir.join("c.json"), "not json").unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        
+        let loaded = load_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(loaded.macros.len(), 1);
+        assert_eq!(loaded.errors.len(), 2);
+        assert_eq!(loaded.errors[0].file_name, "b.json");
+        assert!(matches!(&loaded.errors[0].error, UserMacroError::DuplicateName(name) if name == "Tap W"));
+        assert_eq!(loaded.errors[1].file_name, "c.json");
+        assert!(matches!(loaded.errors[1].error, UserMacroError::Syntax(_)));
+    }
+    
+    #[test]
+    fn saved_macros_load_back() {
+        let dir = std::env::temp_dir().join(format!("moonwalk-macros-save-test-{}", std::process::id()));
+        let user_macro = UserMacro {
+            name: "Strafe Left!".to_string(),
+            description: String::new(),
+            steps: vec![
+                MacroStep::KeyDown { key: KeyRef::Vk(0x41) },
+                MacroStep::Wait { ms: 120 },
+                MacroStep::KeyUp { key: KeyRef::Vk(0x41) },
+            ],
+        };
+        
+        let path = save(&dir, &user_macro).unwrap();
+        let second_save = save(&dir, &user_macro);
+        let loaded = load_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(path.file_name().unwrap(), "strafe-left.json");
+        assert!(matches!(second_save, Err(UserMacroError::AlreadyExists(existing)) if existing == path));
+        assert_eq!(loaded.macros, [user_macro]);
+    }
+}
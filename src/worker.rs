@@ -0,0 +1,387 @@
+// Runs macros on a dedicated thread so the UI keeps repainting while they sleep
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use eframe::egui;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::config::{Config, GameKeymap};
+use crate::error::{InputError, MacroError};
+use crate::input::{self, InputBackend, InputBackendKind};
+use crate::macros::{CancelToken, MacroExecutor, StepTiming};
+use crate::steps::MacroStep;
+
+enum Job {
+    // Boxed to keep the channel's messages small
+    Run { name: String, steps: Vec<MacroStep>, config: Box<Config>, triggered_at: Instant },
+    ReleaseAll { backend: InputBackendKind, keymap: GameKeymap },
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub enum WorkerEvent {
+    Started(String),
+    Progress(String),
+    /// `Ok` carries the name of the input backend that was used. `latency` is the time from
+    /// the trigger to the first key reaching the backend, if the macro sent any, and `timings`
+    /// says how late each key step was against its deadline.
+    Finished {
+        name: String,
+        result: Result<&'static str, MacroError>,
+        latency: Option<Duration>,
+        timings: Vec<StepTiming>,
+    },
+    /// Stopped early by `abort`, with any held keys already released
+    Aborted(String),
+    KeysReleased(Result<(), MacroError>),
+}
+
+/// Opens the input backend a job asks for; tests substitute a recording backend
+pub type BackendFactory = Box<dyn Fn(InputBackendKind) -> Result<Box<dyn InputBackend>, InputError> + Send>;
+
+/// Cheap to clone handle for starting and stopping macros from any thread
+#[derive(Clone)]
+pub struct WorkerHandle {
+    jobs: Sender<Job>,
+    /// Name of the macro executing, cleared by the worker thread when it ends
+    running: Arc<Mutex<Option<String>>>,
+    cancel: CancelToken,
+}
+
+impl WorkerHandle {
+    /// Macro currently executing, if any
+    pub fn running(&self) -> Option<String> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    /// Queues a macro; `triggered_at` is when the user asked for it, for latency reporting
+    pub fn submit(&self, name: &str, steps: Vec<MacroStep>, config: Config, triggered_at: Instant) -> Result<(), MacroError> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(running) = running.as_ref() {
+            return Err(MacroError::Busy(running.clone()));
+        }
+        
+        self.cancel.reset();
+        self.jobs.send(Job::Run { name: name.to_string(), steps, config: Box::new(config), triggered_at })
+            .map_err(|_| MacroError::WorkerStopped)?;
+        *running = Some(name.to_string());
+        Ok(())
+    }
+    
+    /// Stops the running macro at its next checkpoint. Returns false if nothing was running.
+    pub fn abort(&self) -> bool {
+        if self.running().is_none() {
+            return false;
+        }
+        self.cancel.cancel();
+        true
+    }
+    
+    /// Aborts whatever is running, then releases every key the macros could be holding
+    pub fn release_all_keys(&self, config: &Config) -> Result<(), MacroError> {
+        self.abort();
+        self.jobs.send(Job::ReleaseAll { backend: config.input_backend, keymap: config.keymap })
+            .map_err(|_| MacroError::WorkerStopped)
+    }
+}
+
+pub struct MacroWorker {
+    handle: WorkerHandle,
+    events: Receiver<WorkerEvent>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MacroWorker {
+    /// Starts the worker thread; `ctx` is used to wake the UI whenever there is news
+    pub fn spawn(ctx: egui::Context) -> Self {
+        Self::with_backends(ctx, Box::new(input::create_backend))
+    }
+    
+    /// Like `spawn`, opening backends through `backends` instead of the real devices
+    pub fn with_backends(ctx: egui::Context, backends: BackendFactory) -> Self {
+        let (job_tx, job_rx) = unbounded();
+        let (event_tx, event_rx) = unbounded();
+        let handle = WorkerHandle {
+            jobs: job_tx,
+            running: Arc::new(Mutex::new(None)),
+            cancel: CancelToken::default(),
+        };
+        
+        let worker_cancel = handle.cancel.clone();
+        let worker_running = handle.running.clone();
+        let thread = thread::Builder::new()
+            .name("macro-worker".to_string())
+            .spawn(move || run_worker(job_rx, event_tx, worker_cancel, worker_running, ctx, backends))
+            .expect("failed to spawn macro worker thread");
+        
+        Self {
+            handle,
+            events: event_rx,
+            thread: Some(thread),
+        }
+    }
+    
+    /// A handle other threads can use to run macros on this worker
+    pub fn handle(&self) -> WorkerHandle {
+        self.handle.clone()
+    }
+    
+    /// Macro currently executing, if any
+    pub fn running(&self) -> Option<String> {
+        self.handle.running()
+    }
+    
+    pub fn submit(&self, name: &str, steps: Vec<MacroStep>, config: Config, triggered_at: Instant) -> Result<(), MacroError> {
+        self.handle.submit(name, steps, config, triggered_at)
+    }
+    
+    pub fn abort(&self) -> bool {
+        self.handle.abort()
+    }
+    
+    pub fn release_all_keys(&self, config: &Config) -> Result<(), MacroError> {
+        self.handle.release_all_keys(config)
+    }
+    
+    /// Drains everything the worker reported since the last call
+    pub fn poll(&mut self) -> Vec<WorkerEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for MacroWorker {
+    fn drop(&mut self) {
+        // Cloned handles keep the job channel open, so ask the loop to end explicitly.
+        // Cancelling makes the current macro finish quickly.
+        self.handle.cancel.cancel();
+        let _ = self.handle.jobs.send(Job::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Owns the executor on the worker thread, recreating it when the selected backend changes
+struct WorkerState {
+    events: Sender<WorkerEvent>,
+    cancel: CancelToken,
+    running: Arc<Mutex<Option<String>>>,
+    ctx: egui::Context,
+    backends: BackendFactory,
+    // Kept between jobs, creating a uinput device or X connection each time is slow
+    executor: Option<(InputBackendKind, MacroExecutor)>,
+}
+
+impl WorkerState {
+    fn notify(&self, event: WorkerEvent) {
+        let _ = self.events.send(event);
+        self.ctx.request_repaint();
+    }
+    
+    fn executor(&mut self, backend: InputBackendKind) -> Result<&mut MacroExecutor, MacroError> {
+        if self.executor.as_ref().map(|(kind, _)| *kind) != Some(backend) {
+            self.executor = None; // Release the old device before opening a new one
+            let mut executor = MacroExecutor::with_backend((self.backends)(backend)?);
+            executor.set_cancel_token(self.cancel.clone());
+            
+            let events = self.events.clone();
+            let ctx = self.ctx.clone();
+            executor.set_progress_callback(Box::new(move |message| {
+                let _ = events.send(WorkerEvent::Progress(message.to_string()));
+                ctx.request_repaint();
+            }));
+            self.executor = Some((backend, executor));
+        }
+        
+        Ok(self.executor.as_mut().map(|(_, executor)| executor).expect("executor was just created"))
+    }
+    
+    fn run(&mut self, name: String, steps: &[MacroStep], config: &Config, triggered_at: Instant) {
+        self.notify(WorkerEvent::Started(name.clone()));
+        
+        let mut latency = None;
+        let mut timings = Vec::new();
+        let result = self.executor(config.input_backend).and_then(|executor| {
+            // The executor's key guard has already released held keys by the time a panic lands here
+            let run = || executor.run_steps(&name, steps, config, triggered_at);
+            let result = panic::catch_unwind(AssertUnwindSafe(run))
+                .unwrap_or_else(|payload| Err(MacroError::Panicked(panic_message(&payload).to_string())));
+            latency = executor.first_input_at().map(|at| at.saturating_duration_since(triggered_at));
+            timings = executor.timings().to_vec();
+            result.map(|()| executor.backend_name())
+        });
+        
+        // Read the flag while the slot is still taken: a submit after it is freed resets the token
+        let aborted = matches!(result, Err(MacroError::Cancelled)) || (result.is_err() && self.cancel.is_cancelled());
+        // Free the slot before reporting, so the next trigger isn't refused as still running
+        *self.running.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        
+        if aborted {
+            self.notify(WorkerEvent::Aborted(name));
+        } else {
+            self.notify(WorkerEvent::Finished { name, result, latency, timings });
+        }
+    }
+    
+    fn release_all(&mut self, backend: InputBackendKind, keymap: &GameKeymap) {
+        let result = self.executor(backend).and_then(|executor| executor.release_all_keys(keymap));
+        self.notify(WorkerEvent::KeysReleased(result));
+    }
+}
+
+fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+fn run_worker(
+    jobs: Receiver<Job>,
+    events: Sender<WorkerEvent>,
+    cancel: CancelToken,
+    running: Arc<Mutex<Option<String>>>,
+    ctx: egui::Context,
+    backends: BackendFactory,
+) {
+    let mut state = WorkerState { events, cancel, running, ctx, backends, executor: None };
+    
+    for job in jobs {
+        match job {
+            Job::Run { name, steps, config, triggered_at } => state.run(name, &steps, &config, triggered_at),
+            Job::ReleaseAll { backend, keymap } => state.release_all(backend, &keymap),
+            Job::Shutdown => break,
+        }
+    }
+    
+    // Dropping the executor here releases anything still held on shutdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::recording::{Fault, InputEvent::{KeyDown, KeyUp}, RecordingBackend};
+    use crate::input::{VK_LSHIFT, VK_W};
+    use crate::steps::KeyRef;
+    
+    fn worker(recorder: &RecordingBackend) -> MacroWorker {
+        let recorder = recorder.clone();
+        MacroWorker::with_backends(egui::Context::default(), Box::new(move |_| Ok(Box::new(recorder.clone()))))
+    }
+    
+    /// Holds W, then waits long enough for the test to act while the macro runs
+    fn hold_w() -> Vec<MacroStep> {
+        vec![MacroStep::KeyDown { key: KeyRef::Vk(VK_W) }, MacroStep::Wait { ms: 5_000 }]
+    }
+    
+    /// The next event other than `Started` and `Progress`
+    fn outcome(worker: &mut MacroWorker) -> WorkerEvent {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            let event = worker.poll().into_iter().find(|e| !matches!(e, WorkerEvent::Started(_) | WorkerEvent::Progress(_)));
+            if let Some(event) = event {
+                return event;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("worker reported nothing");
+    }
+    
+    fn wait_for_event(recorder: &RecordingBackend, event: input::recording::InputEvent) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !recorder.events().contains(&event) {
+            assert!(Instant::now() < deadline, "{:?} never sent", event);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    
+    #[test]
+    fn second_submit_is_refused_while_running() {
+        let recorder = RecordingBackend::new();
+        let worker = worker(&recorder);
+        
+        worker.submit("Hold", hold_w(), Config::default(), Instant::now()).unwrap();
+        let second = worker.submit("Other", hold_w(), Config::default(), Instant::now());
+        
+        assert!(matches!(second, Err(MacroError::Busy(name)) if name == "Hold"));
+        assert_eq!(worker.running().as_deref(), Some("Hold"));
+    }
+    
+    #[test]
+    fn finished_run_reports_latency_and_timings() {
+        let recorder = RecordingBackend::new();
+        let mut worker = worker(&recorder);
+        let steps = vec![MacroStep::KeyPress { key: KeyRef::Vk(VK_W) }, MacroStep::KeyPress { key: KeyRef::Vk(VK_W) }];
+        
+        worker.submit("Tap", steps, Config::default(), Instant::now()).unwrap();
+        
+        match outcome(&mut worker) {
+            WorkerEvent::Finished { name, result, latency, timings } => {
+                assert_eq!(name, "Tap");
+                assert_eq!(result.unwrap(), "Recording");
+                assert!(latency.is_some());
+                assert_eq!(timings.len(), 2);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(worker.running(), None);
+        assert_eq!(recorder.events(), [KeyDown(VK_W), KeyUp(VK_W), KeyDown(VK_W), KeyUp(VK_W)]);
+    }
+    
+    #[test]
+    fn panicking_run_is_reported_and_releases_keys() {
+        let recorder = RecordingBackend::new().with_fault(KeyDown(VK_LSHIFT), Fault::Panic);
+        let mut worker = worker(&recorder);
+        let steps = vec![
+            MacroStep::KeyDown { key: KeyRef::Vk(VK_W) },
+            MacroStep::KeyDown { key: KeyRef::Vk(VK_LSHIFT) },
+        ];
+        
+        worker.submit("Boom", steps, Config::default(), Instant::now()).unwrap();
+        
+        assert!(matches!(outcome(&mut worker), WorkerEvent::Finished { result: Err(MacroError::Panicked(_)), .. }));
+        assert_eq!(recorder.events(), [KeyDown(VK_W), KeyUp(VK_W)]);
+        // The worker survives the panic
+        worker.submit("Again", vec![MacroStep::Wait { ms: 1 }], Config::default(), Instant::now()).unwrap();
+        assert!(matches!(outcome(&mut worker), WorkerEvent::Finished { result: Ok(_), .. }));
+    }
+    
+    #[test]
+    fn release_all_sends_every_holdable_key() {
+        let recorder = RecordingBackend::new();
+        let mut worker = worker(&recorder);
+        let config = Config::default();
+        
+        worker.release_all_keys(&config).unwrap();
+        
+        assert!(matches!(outcome(&mut worker), WorkerEvent::KeysReleased(Ok(()))));
+        let expected: Vec<_> = config.keymap.holdable_keys().into_iter().map(KeyUp).collect();
+        assert_eq!(recorder.events(), expected);
+    }
+    
+    #[test]
+    fn aborted_run_is_reported_as_aborted() {
+        let recorder = RecordingBackend::new();
+        let mut worker = worker(&recorder);
+        
+        worker.submit("Hold", hold_w(), Config::default(), Instant::now()).unwrap();
+        wait_for_event(&recorder, KeyDown(VK_W));
+        worker.abort();
+        
+        assert!(matches!(outcome(&mut worker), WorkerEvent::Aborted(name) if name == "Hold"));
+        assert_eq!(recorder.events(), [KeyDown(VK_W), KeyUp(VK_W)]);
+    }
+    
+    #[test]
+    fn dropping_the_worker_releases_held_keys() {
+        let recorder = RecordingBackend::new();
+        let worker = worker(&recorder);
+        
+        worker.submit("Hold", hold_w(), Config::default(), Instant::now()).unwrap();
+        wait_for_event(&recorder, KeyDown(VK_W));
+        let dropped_at = Instant::now();
+        drop(worker);
+        
+        assert!(dropped_at.elapsed() < Duration::from_secs(1));
+        assert_eq!(recorder.events(), [KeyDown(VK_W), KeyUp(VK_W)]);
+    }
+}